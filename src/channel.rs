@@ -1,4 +1,9 @@
-use crate::model;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{config::Notify, model, twitch};
 
 #[derive(Copy, Clone, Debug)]
 enum ChannelState {
@@ -8,11 +13,54 @@ enum ChannelState {
     UnreadMentions,
 }
 
+/// Whether a message we're about to send would get past the room's restrictions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SendCheck {
+    Allowed,
+    /// We might not qualify, but can't tell for sure
+    Unsure(String),
+    /// We definitely don't qualify, so the message would be dropped
+    Denied(String),
+}
+
+/// Whether `name` is a twitch channel rather than a pseudo-tab, see [`Channel::is_channel`]
+pub fn is_channel(name: &str) -> bool {
+    name.starts_with('#')
+}
+
+/// Whether `name`, with or without its `#`, could be a twitch channel
+///
+/// Those are 1 to 25 letters, digits or underscores, anything else would make a malformed JOIN
+pub fn is_valid_name(name: &str) -> bool {
+    let name = name.strip_prefix('#').unwrap_or(name);
+    (1..=25).contains(&name.len()) && name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
+}
+
 #[derive(Debug)]
 pub struct Channel {
     pub name: String,
+    /// Shown on the tab instead of the name
+    pub alias: Option<String>,
     pub buffer: Option<String>,
-    pub messages: Vec<model::AnaMessage>,
+    pub messages: VecDeque<model::AnaMessage>,
+    /// How many messages up from the newest one we've scrolled, `0` follows new messages
+    pub scroll: usize,
+    /// Messages that arrived while scrolled up
+    pub unseen: usize,
+    pub room_state: twitch::RoomState,
+    /// Our roles in this channel, `None` until the server tells us
+    pub our_roles: Option<twitch::Roles>,
+    /// Messages matching this are dropped
+    pub filter: Option<regex::Regex>,
+    /// Which messages ring the bell, `None` for the configured default
+    pub notify: Option<Notify>,
+    /// Along with their roles as of their last message
+    recent_chatters: VecDeque<(String, twitch::Roles)>,
+    /// Others joining and leaving since `membership_since`, summarized rather than shown one by one
+    joins: usize,
+    parts: usize,
+    membership_since: Option<Instant>,
+    last_sent: Option<(String, Instant)>,
     state: ChannelState,
 }
 
@@ -20,12 +68,37 @@ impl Channel {
     pub fn new(name: impl ToString) -> Self {
         Self {
             name: name.to_string(),
+            alias: None,
             buffer: None,
-            messages: Vec::new(),
+            messages: VecDeque::new(),
+            scroll: 0,
+            unseen: 0,
+            room_state: twitch::RoomState::default(),
+            our_roles: None,
+            filter: None,
+            notify: None,
+            recent_chatters: VecDeque::with_capacity(Self::MAX_RECENT_CHATTERS),
+            joins: 0,
+            parts: 0,
+            membership_since: None,
+            last_sent: None,
             state: ChannelState::Active,
         }
     }
 
+    /// Whether this is a twitch channel, pseudo-tabs (like whispers) can't be joined, parted or sent to
+    pub fn is_channel(&self) -> bool {
+        is_channel(&self.name)
+    }
+
+    /// What the tab shows, the alias if there is one
+    pub fn label(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+
+    /// How many chatters are remembered before the least recent one is evicted
+    pub const MAX_RECENT_CHATTERS: usize = 100;
+
     pub fn set_inactive(&mut self) {
         self.state = ChannelState::Inactive
     }
@@ -42,7 +115,200 @@ impl Channel {
         self.state = ChannelState::UnreadMentions
     }
 
-    pub fn push_message(&mut self, msg: impl Into<model::AnaMessage>) {
-        self.messages.push(msg.into())
+    /// Adds a message, dropping the oldest ones so at most `scrollback` are kept
+    pub fn push_message(&mut self, msg: impl Into<model::AnaMessage>, scrollback: usize) {
+        self.keep_scroll();
+        self.messages.push_back(msg.into());
+        while self.messages.len() > scrollback {
+            self.messages.pop_front();
+        }
+    }
+
+    /// Keeps our place for a new message, if we're scrolled up
+    pub fn keep_scroll(&mut self) {
+        if self.scroll > 0 {
+            self.scroll += 1;
+            self.unseen += 1;
+        }
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        self.scroll = self.scroll.saturating_add_signed(delta);
+        if self.scroll == 0 {
+            self.unseen = 0;
+        }
+    }
+
+    pub fn is_filtered(&self, data: &str) -> bool {
+        self.filter
+            .as_ref()
+            .is_some_and(|filter| filter.is_match(data))
+    }
+
+    pub fn mark_sent(&mut self, data: &str) {
+        self.last_sent = Some((data.to_string(), Instant::now()));
+    }
+
+    /// Whether twitch would drop `data` for being identical to our last message
+    pub fn is_duplicate(&self, data: &str) -> bool {
+        const WINDOW: Duration = Duration::from_secs(30);
+        matches!(&self.last_sent, Some((last, at)) if last == data && at.elapsed() < WINDOW)
+    }
+
+    /// How long until we can send another message, if the channel is in slow mode
+    pub fn slow_mode_remaining(&self) -> Option<Duration> {
+        if self.our_roles.is_some_and(|roles| roles.is_privileged()) {
+            return None;
+        }
+
+        let slow = self.room_state.slow.filter(|&slow| slow > 0)?;
+        let elapsed = self.last_sent.as_ref()?.1.elapsed();
+        Duration::from_secs(slow)
+            .checked_sub(elapsed)
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn check_restrictions(&self) -> SendCheck {
+        let roles = self.our_roles;
+        if roles.is_some_and(|roles| roles.is_privileged()) {
+            return SendCheck::Allowed;
+        }
+
+        if self.room_state.subs_only == Some(true) {
+            match roles {
+                Some(roles) if roles.subscriber => {}
+                Some(..) => {
+                    return SendCheck::Denied(String::from(
+                        "this channel is sub-only and you're not subscribed",
+                    ))
+                }
+                None => {
+                    return SendCheck::Unsure(String::from(
+                        "this channel is sub-only and you might not be subscribed",
+                    ))
+                }
+            }
+        }
+
+        if self.room_state.emote_only == Some(true) {
+            return SendCheck::Unsure(String::from(
+                "this channel is emote-only, messages with text will be dropped",
+            ));
+        }
+
+        if let Some(minutes) = self.room_state.followers_only.filter(|&m| m >= 0) {
+            if !roles.is_some_and(|roles| roles.vip) {
+                let msg = match minutes {
+                    0 => String::from("this channel is followers-only"),
+                    n => format!("this channel is followers-only (followed for at least {n}m)"),
+                };
+                return SendCheck::Unsure(msg);
+            }
+        }
+
+        SendCheck::Allowed
+    }
+
+    /// Marks `name` as the most recent chatter, evicting the least recent one if we're at capacity
+    pub fn add_recent_chatter(&mut self, name: &str, roles: twitch::Roles) {
+        if let Some(pos) = self.recent_chatters.iter().position(|(c, _)| c == name) {
+            if let Some((name, _)) = self.recent_chatters.remove(pos) {
+                self.recent_chatters.push_back((name, roles));
+            }
+            return;
+        }
+
+        if self.recent_chatters.len() == Self::MAX_RECENT_CHATTERS {
+            self.recent_chatters.pop_front();
+        }
+        self.recent_chatters.push_back((name.to_string(), roles));
+    }
+
+    /// The roles of a recent chatter as of their last message, ignoring the case of `name`
+    pub fn chatter_roles(&self, name: &str) -> Option<twitch::Roles> {
+        self.recent_chatters
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(name))
+            .map(|&(_, roles)| roles)
+    }
+
+    /// Counts someone else joining (or leaving) for the next summary
+    pub fn add_membership(&mut self, joined: bool) {
+        self.membership_since.get_or_insert_with(Instant::now);
+        if joined {
+            self.joins += 1
+        } else {
+            self.parts += 1
+        }
+    }
+
+    /// The joins and parts counted so far, once `window` has passed since the first of them
+    pub fn take_membership(&mut self, window: Duration) -> Option<(usize, usize)> {
+        if !self
+            .membership_since
+            .is_some_and(|since| since.elapsed() >= window)
+        {
+            return None;
+        }
+
+        self.membership_since = None;
+        Some((
+            std::mem::take(&mut self.joins),
+            std::mem::take(&mut self.parts),
+        ))
+    }
+
+    /// Forgets who's been chatting and joining, once it can't be trusted to still be accurate
+    pub fn reset_chatters(&mut self) {
+        self.recent_chatters.clear();
+        self.joins = 0;
+        self.parts = 0;
+        self.membership_since = None;
+    }
+
+    /// The recent chatters in this channel, from least to most recent
+    pub fn recent_chatters(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.recent_chatters.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names() {
+        for name in [
+            "museun",
+            "#museun",
+            "a",
+            "Some_User_42",
+            "x".repeat(25).as_str(),
+        ] {
+            assert!(is_valid_name(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn only_one_leading_hash_is_allowed() {
+        assert!(!is_valid_name("#"));
+        assert!(!is_valid_name("##museun"));
+        assert!(!is_valid_name("mu#seun"));
+    }
+
+    #[test]
+    fn invalid_names() {
+        for name in [
+            "",
+            "two words",
+            " museun",
+            "museun\r\nPART #museun",
+            "mu\x07seun",
+            "mu-seun",
+            "müseun",
+            "x".repeat(26).as_str(),
+        ] {
+            assert!(!is_valid_name(name), "{name:?}");
+        }
     }
 }
@@ -0,0 +1,96 @@
+/// Tab-completion of the word before the cursor, cycling through the matching candidates
+#[derive(Debug)]
+pub struct Completion {
+    /// The input before the word being completed
+    head: String,
+    candidates: Vec<String>,
+    index: usize,
+    /// The input as we last completed it, any other input means the user has edited it
+    current: String,
+}
+
+impl Completion {
+    /// Starts completing the last word of `input`
+    ///
+    /// A leading `/` on the first word completes `commands`, otherwise `names` are completed.
+    /// Candidates keep the order they're given in, and `None` is returned if nothing matches
+    pub fn new<'a>(
+        input: &str,
+        commands: impl IntoIterator<Item = &'a str>,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Option<Self> {
+        let start = input.rfind(' ').map_or(0, |pos| pos + 1);
+        let (head, word) = input.split_at(start);
+
+        let (sigil, prefix, candidates): (_, _, Vec<&str>) = match word.strip_prefix('/') {
+            Some(prefix) if start == 0 => ("/", prefix, commands.into_iter().collect()),
+            _ => match word.strip_prefix('@') {
+                Some(prefix) => ("@", prefix, names.into_iter().collect()),
+                None => ("", word, names.into_iter().collect()),
+            },
+        };
+
+        if prefix.is_empty() && sigil != "/" {
+            return None;
+        }
+
+        let prefix = prefix.to_lowercase();
+        let mut seen = std::collections::HashSet::new();
+        let candidates = candidates
+            .into_iter()
+            .filter(|candidate| candidate.to_lowercase().starts_with(&prefix))
+            .filter(|candidate| seen.insert(candidate.to_lowercase()))
+            .map(|candidate| format!("{sigil}{candidate}"))
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            head: head.to_string(),
+            candidates,
+            index: usize::MAX,
+            current: String::new(),
+        })
+    }
+
+    /// Moves to the next (or previous) candidate, returning the completed input
+    pub fn cycle(&mut self, forward: bool) -> &str {
+        let len = self.candidates.len();
+        self.index = match (self.index, forward) {
+            (usize::MAX, true) => 0,
+            (usize::MAX, false) => len - 1,
+            (index, true) => (index + 1) % len,
+            (index, false) => (index + len - 1) % len,
+        };
+
+        self.current = format!("{}{} ", self.head, self.candidates[self.index]);
+        &self.current
+    }
+
+    /// Whether `input` is still what we last completed it to
+    pub fn is_current(&self, input: &str) -> bool {
+        self.current == input
+    }
+
+    /// The candidates, with the selected one in brackets, e.g. `foo [bar] baz`
+    pub fn display(&self) -> String {
+        if self.candidates.len() == 1 {
+            return String::new();
+        }
+
+        self.candidates
+            .iter()
+            .enumerate()
+            .map(|(i, candidate)| {
+                if i == self.index {
+                    format!("[{candidate}]")
+                } else {
+                    candidate.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
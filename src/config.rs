@@ -0,0 +1,251 @@
+use std::path::PathBuf;
+
+use anathema::core::Color;
+use unicode_width::UnicodeWidthStr;
+
+use crate::timestamp::{TimestampFormat, Zone};
+
+/// What to do when sending the same message twice in a row
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateMessages {
+    /// Make the message unique with an invisible character
+    #[default]
+    Bypass,
+    /// Don't send it, and tell the user why
+    Warn,
+}
+
+/// What to do with messages too long to fit on one line
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LongMessages {
+    /// Wrap them onto as many lines as they need
+    #[default]
+    Wrap,
+    /// Cut them off with an ellipsis
+    Truncate,
+}
+
+/// Which messages ring the terminal bell, set per channel with `/notify`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Notify {
+    All,
+    /// Messages mentioning us, and whispers
+    #[default]
+    Mentions,
+    None,
+}
+
+impl Notify {
+    pub fn parse(val: &str) -> Option<Self> {
+        match val {
+            "all" => Some(Self::All),
+            "mentions" => Some(Self::Mentions),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Mentions => "mentions",
+            Self::None => "none",
+        }
+    }
+}
+
+/// The colors tabs show whether they're active, or have unread messages or mentions with
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Palette {
+    #[default]
+    Default,
+    /// Colors that most kinds of color blindness can tell apart, with a glyph for unread tabs too
+    ColorBlind,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub timestamp: TimestampFormat,
+    /// An optional template to use instead of the embedded one
+    pub template: Option<PathBuf>,
+    /// Hold back messages sent before a channel's slow mode cooldown has elapsed
+    pub slow_mode_block: bool,
+    /// Mark our own messages so they stand out
+    pub highlight_own: bool,
+    /// Mark someone's first ever message in a channel, so they can be greeted
+    pub highlight_first: bool,
+    /// Color names by the sender's role (broadcaster, moderator, ...) rather than their chosen color
+    pub role_colors: bool,
+    /// Leave off the name of someone sending several messages in a row
+    pub group_messages: bool,
+    /// Count repeats of the same message from the same sender on one line
+    pub collapse_repeats: bool,
+    pub duplicate_messages: DuplicateMessages,
+    pub long_messages: LongMessages,
+    /// Where the session (joined channels, tab order) is saved, `None` to not save it
+    pub session: Option<PathBuf>,
+    /// How many messages each channel keeps
+    ///
+    /// Every message stays in memory (along with its formatted timestamp) until it falls off the end,
+    /// so a larger scrollback costs memory for every joined channel, busy ones fill it quickly
+    pub scrollback: usize,
+    /// Disconnect after this long without any input, until the next key press
+    pub idle_disconnect: Option<std::time::Duration>,
+    /// How often joins and leaves are summarized in each channel, `None` to not show them
+    pub membership_summary: Option<std::time::Duration>,
+    pub palette: Palette,
+    /// Dim all but the newest few visible lines, so recent activity stands out
+    pub fade_older: bool,
+    /// Which messages ring the bell in channels without their own setting
+    pub notify: Notify,
+    /// Ctrl+C only quits when pressed twice in a row, so it isn't done by accident
+    pub confirm_quit: bool,
+    /// Show each tab's Ctrl+number shortcut before its name
+    pub tab_numbers: bool,
+    /// Use plain characters rather than emoji, for terminals that can't show them
+    pub ascii_glyphs: bool,
+    /// Drawn at the end of the input, this must be a single column wide
+    pub cursor: String,
+    /// The color of the cursor, and of the character under it
+    pub cursor_color: Color,
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let zone = match get("ANACHAT_TIMEZONE") {
+            Some(zone) => Zone::parse(&zone).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "`ANACHAT_TIMEZONE` must be `local`, `utc` or an offset like `+02:00`"
+                )
+            })?,
+            None => Zone::default(),
+        };
+
+        let duplicate_messages = match get("ANACHAT_DUPLICATE_MESSAGES").as_deref() {
+            None | Some("bypass") => DuplicateMessages::Bypass,
+            Some("warn") => DuplicateMessages::Warn,
+            Some(..) => anyhow::bail!("`ANACHAT_DUPLICATE_MESSAGES` must be `bypass` or `warn`"),
+        };
+
+        // `get` ignores whitespace only values, but a space is a fine (if invisible) cursor
+        let cursor = std::env::var("ANACHAT_CURSOR").unwrap_or_else(|_| String::from("█"));
+        if cursor.width() != 1 {
+            anyhow::bail!("`ANACHAT_CURSOR` must be a single column wide, like `█` or `▋`")
+        }
+
+        let long_messages = match get("ANACHAT_LONG_MESSAGES").as_deref() {
+            None | Some("wrap") => LongMessages::Wrap,
+            Some("truncate") => LongMessages::Truncate,
+            Some(..) => anyhow::bail!("`ANACHAT_LONG_MESSAGES` must be `wrap` or `truncate`"),
+        };
+
+        let palette = match get("ANACHAT_PALETTE").as_deref() {
+            None | Some("default") => Palette::Default,
+            Some("colorblind") => Palette::ColorBlind,
+            Some(..) => anyhow::bail!("`ANACHAT_PALETTE` must be `default` or `colorblind`"),
+        };
+
+        let notify = match get("ANACHAT_NOTIFY") {
+            Some(notify) => Notify::parse(&notify).ok_or_else(|| {
+                anyhow::anyhow!("`ANACHAT_NOTIFY` must be `all`, `mentions` or `none`")
+            })?,
+            None => Notify::default(),
+        };
+
+        let format = get("ANACHAT_TIMESTAMP_FORMAT");
+        let format = format.as_deref().unwrap_or(TimestampFormat::DEFAULT_FORMAT);
+
+        Ok(Self {
+            timestamp: TimestampFormat::new(
+                format,
+                zone,
+                flag("ANACHAT_RELATIVE_TIMESTAMPS", false)?,
+            ),
+            template: get("ANACHAT_TEMPLATE").map(PathBuf::from),
+            slow_mode_block: flag("ANACHAT_SLOW_MODE_BLOCK", true)?,
+            highlight_own: flag("ANACHAT_HIGHLIGHT_OWN", false)?,
+            highlight_first: flag("ANACHAT_HIGHLIGHT_FIRST", true)?,
+            role_colors: flag("ANACHAT_ROLE_COLORS", false)?,
+            group_messages: flag("ANACHAT_GROUP_MESSAGES", false)?,
+            collapse_repeats: flag("ANACHAT_COLLAPSE_REPEATS", false)?,
+            duplicate_messages,
+            long_messages,
+            session: match get("ANACHAT_SESSION") {
+                Some(path) if matches!(&*path, "off" | "none") => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(PathBuf::from(".anachat.session")),
+            },
+            scrollback: number("ANACHAT_SCROLLBACK", 1000)?.max(1),
+            idle_disconnect: match number("ANACHAT_IDLE_DISCONNECT", 0)? {
+                0 => None,
+                minutes => Some(std::time::Duration::from_secs(minutes as u64 * 60)),
+            },
+            membership_summary: match number("ANACHAT_MEMBERSHIP_SUMMARY", 60)? {
+                0 => None,
+                secs => Some(std::time::Duration::from_secs(secs as u64)),
+            },
+            palette,
+            fade_older: flag("ANACHAT_FADE_OLDER", false)?,
+            notify,
+            confirm_quit: flag("ANACHAT_CONFIRM_QUIT", true)?,
+            tab_numbers: flag("ANACHAT_TAB_NUMBERS", false)?,
+            ascii_glyphs: flag("ANACHAT_ASCII_GLYPHS", false)?,
+            cursor,
+            cursor_color: color(
+                "ANACHAT_CURSOR_COLOR",
+                Color::Rgb {
+                    r: 0,
+                    g: 170,
+                    b: 170,
+                },
+            )?,
+        })
+    }
+}
+
+fn get(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|s| !s.trim().is_empty())
+}
+
+fn number(key: &str, default: usize) -> anyhow::Result<usize> {
+    let Some(val) = get(key) else {
+        return Ok(default);
+    };
+
+    val.trim()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("`{key}` must be a positive number"))
+}
+
+/// A `#rgb` or `#rrggbb` color
+fn color(key: &str, default: Color) -> anyhow::Result<Color> {
+    let Some(val) = get(key) else {
+        return Ok(default);
+    };
+
+    let hex = val.trim().trim_start_matches('#');
+    let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.is_ascii());
+    let (r, g, b) = match (hex.len(), rgb) {
+        // each digit is repeated, `#0aa` is `#00aaaa`
+        (3, Some(rgb)) => (
+            ((rgb >> 8) & 0xf) as u8 * 0x11,
+            ((rgb >> 4) & 0xf) as u8 * 0x11,
+            (rgb & 0xf) as u8 * 0x11,
+        ),
+        (6, Some(rgb)) => ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8),
+        _ => anyhow::bail!("`{key}` must be a color like `#0aa` or `#00aaaa`"),
+    };
+    Ok(Color::Rgb { r, g, b })
+}
+
+fn flag(key: &str, default: bool) -> anyhow::Result<bool> {
+    let Some(val) = get(key) else {
+        return Ok(default);
+    };
+
+    match &*val.trim().to_ascii_lowercase() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => anyhow::bail!("`{key}` must be a boolean (`true` or `false`)"),
+    }
+}
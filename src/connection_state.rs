@@ -0,0 +1,38 @@
+use anathema::values::ValueRef;
+
+/// What the UI should show, the template switches on [`ConnectionState::as_str`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    Reconnecting,
+    InvalidAuth,
+    OnNoChannels,
+    GaveUp,
+    /// Disconnected after being idle, until the next key press
+    Idle,
+    /// The whole replay was shown, until it's started over
+    ReplayEnded,
+}
+
+impl ConnectionState {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Reconnecting => "reconnecting",
+            Self::InvalidAuth => "invalid_auth",
+            Self::OnNoChannels => "on_no_channels",
+            Self::GaveUp => "gave_up",
+            Self::Idle => "idle",
+            Self::ReplayEnded => "replay_ended",
+        }
+    }
+}
+
+impl<'a> From<&'a ConnectionState> for ValueRef<'a> {
+    fn from(value: &'a ConnectionState) -> Self {
+        ValueRef::Str(value.as_str())
+    }
+}
@@ -1,45 +1,169 @@
 use anathema::{core::Color, values::StateValue};
 
+use crate::config::Palette;
+
+/// What a tab shows about its channel, see [`DisplayChannel::status`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TabState {
+    Active,
+    Inactive,
+    Unread,
+    Mentions,
+}
+
 #[derive(Debug, anathema::values::State)]
 pub struct DisplayChannel {
+    /// The color of the tab's state, from its [`Palette`]
     pub status: StateValue<Color>,
+    /// A glyph for the tab's state shown before the label, for palettes that don't rely on color alone
+    pub marker: StateValue<String>,
     pub name: StateValue<String>,
+    /// What the tab shows, the name unless the channel has an alias
+    pub label: StateValue<String>,
+    /// Glyphs for the channel's restrictions, shown after the label
+    pub modes: StateValue<String>,
+    /// The tab's Ctrl+number shortcut shown before the label, e.g. `1:`, empty if they're not shown
+    pub number: StateValue<String>,
+    pub visible: StateValue<bool>,
+    /// The unread counts shown after the name, e.g. ` (3)` or ` (3, 1!)`
+    pub unread: StateValue<String>,
+    unread_messages: usize,
+    unread_mentions: usize,
+    state: TabState,
+    palette: Palette,
 }
 
 impl DisplayChannel {
-    const ACTIVE: Color = Color::Yellow;
-    const INACTIVE: Color = Color::Grey;
-    const UNREAD: Color = Color::Blue;
-    const MENTIONS: Color = Color::Green;
-
-    pub fn new(name: impl ToString) -> Self {
+    pub fn new(name: impl ToString, palette: Palette) -> Self {
         Self {
-            status: StateValue::new(Self::ACTIVE),
+            status: StateValue::new(palette.color(TabState::Active)),
+            marker: StateValue::new(palette.marker(TabState::Active).to_string()),
             name: StateValue::new(name.to_string()),
+            label: StateValue::new(name.to_string()),
+            modes: StateValue::default(),
+            number: StateValue::default(),
+            visible: StateValue::new(true),
+            unread: StateValue::default(),
+            unread_messages: 0,
+            unread_mentions: 0,
+            state: TabState::Active,
+            palette,
+        }
+    }
+
+    fn set_state(&mut self, state: TabState) {
+        self.state = state;
+        *self.status = self.palette.color(state);
+        let marker = self.palette.marker(state);
+        if *self.marker != marker {
+            *self.marker = marker.to_string();
+        }
+    }
+
+    /// Counts an unread message, the active tab keeps its color
+    pub fn add_unread(&mut self, mention: bool) {
+        self.unread_messages += 1;
+        if mention {
+            self.unread_mentions += 1;
+        }
+
+        if !self.is_active() {
+            if mention {
+                self.set_unread_mentions();
+            } else if !self.has_mentions() {
+                self.set_unread_messages();
+            }
+        }
+        self.update_unread();
+    }
+
+    /// The unread `(messages, mentions)`
+    pub const fn unread_counts(&self) -> (usize, usize) {
+        (self.unread_messages, self.unread_mentions)
+    }
+
+    /// Restores unread counts from a previous session
+    pub fn restore_unread(&mut self, messages: usize, mentions: usize) {
+        self.unread_messages = messages;
+        self.unread_mentions = mentions;
+        if !self.is_active() {
+            self.set_inactive();
         }
+        self.update_unread();
+    }
+
+    fn has_mentions(&self) -> bool {
+        self.state == TabState::Mentions
+    }
+
+    fn update_unread(&mut self) {
+        *self.unread = match (self.unread_messages, self.unread_mentions) {
+            (0, _) => String::new(),
+            (messages, 0) => format!(" ({messages})"),
+            (messages, mentions) => format!(" ({messages}, {mentions}!)"),
+        };
     }
 
     pub fn is_active(&self) -> bool {
-        matches!(*self.status, Self::ACTIVE)
+        self.state == TabState::Active
     }
 
     pub fn is_inactive(&self) -> bool {
-        matches!(*self.status, Self::INACTIVE)
+        self.state == TabState::Inactive
     }
 
+    /// Marks the tab as not selected, keeping the unread color if it has unread messages
     pub fn set_inactive(&mut self) {
-        *self.status = Self::INACTIVE
+        self.set_state(match self.unread_counts() {
+            (_, 1..) => TabState::Mentions,
+            (1.., _) => TabState::Unread,
+            _ => TabState::Inactive,
+        })
+    }
+
+    /// Marks the messages as read, mentions stay unread until the tab is typed in or switched to
+    pub fn mark_read(&mut self) {
+        self.unread_messages = self.unread_mentions;
+        self.update_unread();
     }
 
     pub fn set_active(&mut self) {
-        *self.status = Self::ACTIVE
+        self.set_state(TabState::Active);
+        self.unread_messages = 0;
+        self.unread_mentions = 0;
+        self.update_unread();
     }
 
     pub fn set_unread_messages(&mut self) {
-        *self.status = Self::UNREAD
+        self.set_state(TabState::Unread)
     }
 
     pub fn set_unread_mentions(&mut self) {
-        *self.status = Self::MENTIONS
+        self.set_state(TabState::Mentions)
+    }
+}
+
+impl Palette {
+    fn color(self, state: TabState) -> Color {
+        let rgb = |r, g, b| Color::Rgb { r, g, b };
+        match (self, state) {
+            (Self::Default, TabState::Active) => Color::Yellow,
+            (Self::Default, TabState::Inactive) => Color::Grey,
+            (Self::Default, TabState::Unread) => Color::Blue,
+            (Self::Default, TabState::Mentions) => Color::Green,
+            // from the Okabe-Ito palette
+            (Self::ColorBlind, TabState::Active) => rgb(0xf0, 0xe4, 0x42),
+            (Self::ColorBlind, TabState::Inactive) => Color::Grey,
+            (Self::ColorBlind, TabState::Unread) => rgb(0x56, 0xb4, 0xe9),
+            (Self::ColorBlind, TabState::Mentions) => rgb(0xd5, 0x5e, 0x00),
+        }
+    }
+
+    fn marker(self, state: TabState) -> &'static str {
+        match (self, state) {
+            (Self::ColorBlind, TabState::Unread) => "·",
+            (Self::ColorBlind, TabState::Mentions) => "*",
+            _ => "",
+        }
     }
 }
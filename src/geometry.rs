@@ -8,6 +8,46 @@ pub const fn pos2(x: u16, y: u16) -> Pos2 {
     Pos2 { x, y }
 }
 
+impl Pos2 {
+    pub const ZERO: Self = pos2(0, 0);
+
+    /// Moves this position by (`dx`, `dy`), saturating at the bounds of a `u16`
+    pub const fn offset(self, dx: i32, dy: i32) -> Self {
+        const fn saturate(val: u16, delta: i32) -> u16 {
+            let val = (val as i32).saturating_add(delta);
+            if val < 0 {
+                0
+            } else if val > u16::MAX as i32 {
+                u16::MAX
+            } else {
+                val as u16
+            }
+        }
+
+        pos2(saturate(self.x, dx), saturate(self.y, dy))
+    }
+}
+
+impl From<(u16, u16)> for Pos2 {
+    fn from((x, y): (u16, u16)) -> Self {
+        pos2(x, y)
+    }
+}
+
+impl std::ops::Add for Pos2 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        pos2(self.x.saturating_add(rhs.x), self.y.saturating_add(rhs.y))
+    }
+}
+
+impl std::ops::Sub for Pos2 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        pos2(self.x.saturating_sub(rhs.x), self.y.saturating_sub(rhs.y))
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct Rect {
     pub min: Pos2,
@@ -26,4 +66,117 @@ impl Rect {
     pub const fn contains_rect(&self, other: Self) -> bool {
         self.contains(other.min) && self.contains(other.max)
     }
+
+    /// The overlapping region of both rects, rects sharing an edge overlap on that edge
+    pub const fn intersect(&self, other: Self) -> Option<Self> {
+        let min = pos2(max(self.min.x, other.min.x), max(self.min.y, other.min.y));
+        let max = pos2(min(self.max.x, other.max.x), min(self.max.y, other.max.y));
+        if min.x <= max.x && min.y <= max.y {
+            Some(Self { min, max })
+        } else {
+            None
+        }
+    }
+
+    /// `min` and `max` are both inclusive, so a rect with `min == max` is 1 wide
+    pub const fn width(&self) -> u16 {
+        self.max.x.saturating_sub(self.min.x).saturating_add(1)
+    }
+
+    /// `min` and `max` are both inclusive, so a rect with `min == max` is 1 high
+    pub const fn height(&self) -> u16 {
+        self.max.y.saturating_sub(self.min.y).saturating_add(1)
+    }
+
+    pub const fn area(&self) -> u32 {
+        self.width() as u32 * self.height() as u32
+    }
+
+    /// The closest point to `pos` inside of this rect
+    pub const fn clamp_point(&self, pos: Pos2) -> Pos2 {
+        pos2(
+            min(max(pos.x, self.min.x), self.max.x),
+            min(max(pos.y, self.min.y), self.max.y),
+        )
+    }
+}
+
+const fn min(a: u16, b: u16) -> u16 {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+const fn max(a: u16, b: u16) -> u16 {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(min: (u16, u16), max: (u16, u16)) -> Rect {
+        Rect::from_min_max(min.into(), max.into())
+    }
+
+    #[test]
+    fn overlapping_rects_intersect() {
+        let a = rect((0, 0), (4, 4));
+        let b = rect((2, 3), (6, 8));
+        assert_eq!(a.intersect(b), Some(rect((2, 3), (4, 4))));
+        assert_eq!(b.intersect(a), Some(rect((2, 3), (4, 4))));
+    }
+
+    #[test]
+    fn contained_rect_is_the_intersection() {
+        let outer = rect((0, 0), (9, 9));
+        let inner = rect((3, 3), (5, 5));
+        assert_eq!(outer.intersect(inner), Some(inner));
+    }
+
+    #[test]
+    fn touching_rects_intersect_on_the_shared_edge() {
+        let a = rect((0, 0), (4, 4));
+        let b = rect((4, 1), (8, 2));
+        assert_eq!(a.intersect(b), Some(rect((4, 1), (4, 2))));
+
+        let corner = rect((4, 4), (6, 6));
+        assert_eq!(a.intersect(corner), Some(rect((4, 4), (4, 4))));
+    }
+
+    #[test]
+    fn disjoint_rects_dont_intersect() {
+        let a = rect((0, 0), (4, 4));
+        assert_eq!(a.intersect(rect((5, 0), (8, 4))), None);
+        assert_eq!(a.intersect(rect((0, 5), (4, 8))), None);
+        assert_eq!(a.intersect(rect((5, 5), (8, 8))), None);
+    }
+
+    #[test]
+    fn adding_saturates_at_the_max() {
+        assert_eq!(pos2(u16::MAX - 1, 3) + pos2(5, 4), pos2(u16::MAX, 7));
+        assert_eq!(
+            pos2(u16::MAX, u16::MAX) + pos2(1, 1),
+            pos2(u16::MAX, u16::MAX)
+        );
+    }
+
+    #[test]
+    fn subtracting_saturates_at_zero() {
+        assert_eq!(pos2(3, 10) - pos2(5, 4), pos2(0, 6));
+        assert_eq!(Pos2::ZERO - pos2(1, 1), Pos2::ZERO);
+    }
+
+    #[test]
+    fn offset_saturates_at_both_ends() {
+        assert_eq!(pos2(2, 2).offset(-5, 3), pos2(0, 5));
+        assert_eq!(pos2(u16::MAX - 2, 2).offset(10, -1), pos2(u16::MAX, 1));
+        assert_eq!(pos2(1, 1).offset(i32::MIN, i32::MAX), pos2(0, u16::MAX));
+    }
 }
@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+
+/// Previously submitted inputs, from oldest to newest
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<String>,
+}
+
+impl History {
+    /// How many inputs are remembered before the oldest one is evicted
+    pub const MAX_ENTRIES: usize = 100;
+
+    pub fn push(&mut self, input: &str) {
+        if input.trim().is_empty() || self.entries.back().is_some_and(|last| last == input) {
+            return;
+        }
+
+        if self.entries.len() == Self::MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(input.to_string());
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// The index of the newest entry before `before` (or any entry) that contains `query`, ignoring case
+    pub fn find(&self, query: &str, before: Option<usize>) -> Option<usize> {
+        let query = query.to_lowercase();
+        self.entries
+            .iter()
+            .take(before.unwrap_or(self.entries.len()))
+            .rposition(|entry| entry.to_lowercase().contains(&query))
+    }
+}
+
+/// An in-progress reverse search through the [`History`]
+#[derive(Debug, Default)]
+pub struct Search {
+    pub query: String,
+    /// The matching entry, if any
+    pub index: Option<usize>,
+    /// The input from before the search, restored if it's cancelled
+    pub original: String,
+}
@@ -0,0 +1,137 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A cursor into the input, always on a grapheme boundary
+#[derive(Debug, Default)]
+pub struct Cursor {
+    pos: usize,
+    /// The length of the input when the cursor last moved, any other length means it was replaced
+    len: usize,
+}
+
+impl Cursor {
+    /// The byte offset of the cursor, the end of `input` if it was replaced since the cursor moved
+    pub fn pos(&self, input: &str) -> usize {
+        if input.len() != self.len || !input.is_char_boundary(self.pos) {
+            return input.len();
+        }
+        self.pos
+    }
+
+    pub fn insert(&mut self, input: &mut String, ch: char) {
+        let pos = self.pos(input);
+        input.insert(pos, ch);
+        // a combining mark joins the grapheme before it, which the cursor is already after
+        self.set(input, pos + ch.len_utf8());
+    }
+
+    /// Removes the grapheme before the cursor
+    pub fn backspace(&mut self, input: &mut String) {
+        let pos = self.pos(input);
+        let start = previous_boundary(input, pos);
+        input.replace_range(start..pos, "");
+        self.set(input, start);
+    }
+
+    pub fn left(&mut self, input: &str) {
+        let pos = previous_boundary(input, self.pos(input));
+        self.set(input, pos);
+    }
+
+    pub fn right(&mut self, input: &str) {
+        let pos = next_boundary(input, self.pos(input));
+        self.set(input, pos);
+    }
+
+    pub fn home(&mut self, input: &str) {
+        self.set(input, 0);
+    }
+
+    /// Splits `input` into what's before the cursor, the grapheme under it and what's after it
+    ///
+    /// What's before is cut from the left so it fits in `columns`, keeping the cursor in view
+    pub fn split<'a>(&self, input: &'a str, columns: usize) -> (&'a str, &'a str, &'a str) {
+        let pos = self.pos(input);
+        let (before, rest) = input.split_at(pos);
+        let under = rest.graphemes(true).next().unwrap_or_default();
+        let after = &rest[under.len()..];
+
+        let mut start = 0;
+        for (index, grapheme) in before.grapheme_indices(true) {
+            if before[start..].width() < columns {
+                break;
+            }
+            start = index + grapheme.len();
+        }
+
+        (&before[start..], under, after)
+    }
+
+    fn set(&mut self, input: &str, pos: usize) {
+        self.pos = pos;
+        self.len = input.len();
+    }
+}
+
+fn previous_boundary(input: &str, pos: usize) -> usize {
+    input[..pos]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(index, _)| index)
+}
+
+fn next_boundary(input: &str, pos: usize) -> usize {
+    input[pos..]
+        .graphemes(true)
+        .next()
+        .map_or(pos, |grapheme| pos + grapheme.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> (Cursor, String) {
+        let mut cursor = Cursor::default();
+        let mut input = String::new();
+        for ch in text.chars() {
+            cursor.insert(&mut input, ch);
+        }
+        (cursor, input)
+    }
+
+    #[test]
+    fn ascii_is_a_column_per_char() {
+        let (mut cursor, input) = typed("hello");
+        assert_eq!(cursor.split(&input, 10), ("hello", "", ""));
+        // the cursor takes a column too
+        assert_eq!(cursor.split(&input, 3), ("lo", "", ""));
+
+        cursor.left(&input);
+        assert_eq!(cursor.split(&input, 10), ("hell", "o", ""));
+    }
+
+    #[test]
+    fn cjk_is_two_columns_per_char() {
+        let (mut cursor, input) = typed("日本語");
+        assert_eq!(cursor.split(&input, 7), ("日本語", "", ""));
+        assert_eq!(cursor.split(&input, 5), ("本語", "", ""));
+
+        cursor.left(&input);
+        assert_eq!(cursor.split(&input, 3), ("本", "語", ""));
+    }
+
+    #[test]
+    fn combining_marks_take_no_columns() {
+        let (mut cursor, mut input) = typed("e\u{301}e\u{301}");
+        assert_eq!(input.width(), 2);
+        assert_eq!(cursor.split(&input, 3), ("e\u{301}e\u{301}", "", ""));
+
+        // the mark moves with the letter it's on
+        cursor.left(&input);
+        assert_eq!(cursor.split(&input, 10), ("e\u{301}", "e\u{301}", ""));
+
+        cursor.backspace(&mut input);
+        assert_eq!(input, "e\u{301}");
+    }
+}
@@ -1,41 +1,151 @@
 mod channel;
+mod completion;
+mod config;
+mod connection_state;
 mod display_channel;
 mod geometry;
+mod history;
+mod input;
 mod model;
 mod root_view;
+mod session;
+mod switcher;
 mod tab;
 mod tabs;
+mod timestamp;
 mod twitch;
 
+const TEMPLATE: &str = include_str!("../templates/root.aml");
+
+/// How long to wait for the connection to close when exiting
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
 fn main() -> anyhow::Result<()> {
     simple_env_load::load_env_from([".secrets.env", ".dev.env"]);
-    let config = twitch::Config::from_env()?;
+    let mut config = twitch::Config::from_env()?;
+    let app_config = config::Config::from_env()?;
+
+    let session = app_config
+        .session
+        .as_deref()
+        .map(session::Session::load)
+        .unwrap_or_default();
+
+    for channel in &session.channels {
+        if !config.channels.contains(channel) {
+            config.channels.push(channel.clone());
+        }
+    }
 
-    anathema::core::Factory::register("tab", tab::TabFactory)?;
+    let tab_factory = tab::TabFactory::default();
+    anathema::core::Factory::register("tab", tab_factory.clone())?;
+
+    // shown while there are no tabs, so it's clear what's being joined
+    let auto_join = config.channels.clone();
 
     let (req_tx, req_rx) = smol::channel::unbounded();
     let (resp_tx, resp_rx) = smol::channel::unbounded();
 
     let handle = std::thread::spawn(move || twitch::connect(config, req_rx, resp_tx));
 
-    let root_view = root_view::RootView {
-        state: root_view::RootState::default(),
-        tabs: tabs::Tabs::default(),
-        feed: resp_rx,
-        send: req_tx.clone(),
+    // only the view that runs leaves its session behind, not the one checking the template
+    let exit_session = std::sync::Arc::new(std::sync::Mutex::new(session.clone()));
+    // the sidebar lists the same labels, so its entries are kept apart from the tabs
+    let make_view = |exit_session| {
+        let regions = tab_factory.regions();
+        let sidebar_regions = tab_factory.regions();
+        root_view::RootView::new(
+            app_config.clone(),
+            session.clone(),
+            exit_session,
+            &auto_join,
+            regions,
+            sidebar_regions,
+            resp_rx.clone(),
+            req_tx.clone(),
+        )
     };
+    let template = load_template(app_config.template.as_deref(), || {
+        make_view(Default::default())
+    });
 
-    let template = std::fs::read_to_string("templates/root.aml")?;
+    let root_view = make_view(exit_session.clone());
     let mut templates = anathema::vm::Templates::new(template, root_view);
     let templates = templates.compile()?;
 
     let mut runtime = anathema::runtime::Runtime::new(&templates)?;
     runtime.enable_alt_screen = false;
 
-    runtime.run()?;
+    // pastes arrive as a single event rather than a key press per character,
+    // and focus changes let mentions only ring the bell while we're in the background
+    let _ = crossterm::execute!(
+        std::io::stdout(),
+        crossterm::event::EnableBracketedPaste,
+        crossterm::event::EnableFocusChange
+    );
+    let result = runtime.run();
+    let _ = crossterm::execute!(
+        std::io::stdout(),
+        crossterm::event::DisableBracketedPaste,
+        crossterm::event::DisableFocusChange
+    );
+
+    if let Some(path) = app_config.session.as_deref() {
+        if let Err(err) = exit_session.lock().unwrap().save(path) {
+            eprintln!("cannot save the session: {err}");
+        }
+    }
 
     // lets ensure the thread ends, we don't care if we can't send to it
     let _ = req_tx.send_blocking(twitch::Request::Disconnect { reconnect: false });
 
-    handle.join().unwrap()
+    // the thread might be stuck connecting or waiting to reconnect, so don't wait on it forever
+    let deadline = std::time::Instant::now() + SHUTDOWN_TIMEOUT;
+    while !handle.is_finished() && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    result?;
+
+    if !handle.is_finished() {
+        eprintln!("the connection didn't close in time, exiting anyway");
+        return Ok(());
+    }
+
+    match handle.join() {
+        Ok(result) => result.map_err(|err| err.context("connection failed")),
+        Err(..) => anyhow::bail!("the connection thread panicked"),
+    }
+}
+
+/// Loads the user-supplied template, if any, falling back to the embedded one if it can't be read or compiled
+fn load_template(
+    path: Option<&std::path::Path>,
+    make_view: impl Fn() -> root_view::RootView,
+) -> String {
+    let Some(path) = path else {
+        return TEMPLATE.to_string();
+    };
+
+    let template = match std::fs::read_to_string(path) {
+        Ok(template) => template,
+        Err(err) => {
+            eprintln!(
+                "cannot read template '{}': {err}. using the default",
+                path.display()
+            );
+            return TEMPLATE.to_string();
+        }
+    };
+
+    match anathema::vm::Templates::new(template.clone(), make_view()).compile() {
+        Ok(..) => template,
+        Err(err) => {
+            eprintln!(
+                "cannot compile template '{}': {err}. using the default",
+                path.display()
+            );
+            TEMPLATE.to_string()
+        }
+    }
 }
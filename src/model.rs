@@ -1,12 +1,184 @@
 use anathema::values::StateValue;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::twitch;
+use crate::{timestamp::TimestampFormat, twitch};
 
 #[derive(Debug, Default, anathema::values::State)]
 pub struct AnaMessage {
     pub sender: StateValue<AnaUser>,
     pub channel: StateValue<String>,
     pub data: StateValue<String>,
+    /// What's drawn of `data`, which is cut short if it's too long and truncated, see [`Self::fit`]
+    ///
+    /// If it mentions us, this is only what comes before the mention
+    pub shown: StateValue<String>,
+    /// The first mention of our name in what's drawn, like `@name`, drawn highlighted
+    pub mention: StateValue<String>,
+    /// What's drawn after the mention
+    pub shown_after: StateValue<String>,
+    /// The color of the message text, dimmed for older lines when they're faded
+    pub text_color: StateValue<anathema::core::Color>,
+    pub timestamp: StateValue<i64>,
+    /// The formatted timestamp and a space after it, empty if timestamps are hidden
+    pub time: StateValue<String>,
+    pub is_system: StateValue<bool>,
+    /// Whether this is a `/me`, shown as `* name text` in the sender's color
+    pub action: StateValue<bool>,
+    /// Rendered before the sender's name, e.g. to mark our own messages
+    pub marker: StateValue<String>,
+    /// Whether this message is within the scrolled view
+    pub visible: StateValue<bool>,
+    /// Whether the sender's name is left off because they also sent the line above
+    pub continued: StateValue<bool>,
+    /// Lines up a continued message with the text of the line above
+    pub indent: StateValue<String>,
+    /// How many times this was sent in a row, when repeats are collapsed
+    pub count: StateValue<usize>,
+    /// Shown after the message once it's repeated, e.g. ` x3`
+    pub repeat: StateValue<String>,
+    /// The server's id for the message, empty if it has none, see [`Self::id`]
+    pub id: StateValue<String>,
+}
+
+impl AnaMessage {
+    /// A message generated by the client rather than sent by a user
+    pub fn system(channel: impl ToString, data: impl ToString) -> Self {
+        Self {
+            channel: StateValue::new(channel.to_string()),
+            data: StateValue::new(data.to_string()),
+            timestamp: StateValue::new(chrono::Utc::now().timestamp_millis()),
+            is_system: StateValue::new(true),
+            text_color: StateValue::new(anathema::core::Color::Reset),
+            visible: StateValue::new(true),
+            count: StateValue::new(1),
+            ..Self::default()
+        }
+    }
+
+    /// The server's id for the message, system lines don't have one
+    pub fn id(&self) -> Option<&str> {
+        Some(self.id.as_str()).filter(|id| !id.is_empty())
+    }
+
+    /// Replaces the text of a message a moderator deleted, it can't be deleted again
+    pub fn delete(&mut self) {
+        *self.data = String::from("<message deleted>");
+        *self.id = String::new();
+    }
+
+    /// Whether `other` is the same chat message from the same sender
+    pub fn repeats(&self, other: &Self) -> bool {
+        !*self.is_system
+            && !*other.is_system
+            && *self.sender.name == *other.sender.name
+            && *self.data == *other.data
+    }
+
+    pub fn add_repeat(&mut self) {
+        *self.count += 1;
+        *self.repeat = format!(" x{}", *self.count);
+    }
+
+    /// Whether this follows `previous` from the same sender, so it can leave off their name
+    pub fn continues(&self, previous: &Self) -> bool {
+        // an action always has the name in it
+        !*self.is_system
+            && !*previous.is_system
+            && !*self.action
+            && *previous.sender.name == *self.sender.name
+    }
+
+    /// Dims the message text, mentions of us stay bright so they still stand out
+    pub fn set_faded(&mut self, faded: bool) {
+        let color = match faded && self.mention.is_empty() {
+            true => anathema::core::Color::DarkGrey,
+            false => anathema::core::Color::Reset,
+        };
+        if *self.text_color != color {
+            *self.text_color = color;
+        }
+    }
+
+    pub fn set_continued(&mut self, continued: bool) {
+        let indent = if continued {
+            // markers like `✨` are wider than a column
+            let width = self.time.width() + self.marker.width() + self.sender.name.width() + 1;
+            " ".repeat(width)
+        } else {
+            String::new()
+        };
+
+        if *self.continued != continued {
+            *self.continued = continued;
+        }
+        if *self.indent != indent {
+            *self.indent = indent;
+        }
+    }
+
+    /// Fits the message in `columns`, returning how many rows it takes
+    ///
+    /// With `truncate` the message is cut off with an ellipsis, rather than wrapped.
+    /// The first mention of `our_name` is split out so it can be highlighted
+    pub fn fit(&mut self, columns: usize, truncate: bool, our_name: &str) -> usize {
+        let columns = columns.max(1);
+        let prefix = if *self.is_system {
+            self.time.width() + "-- ".len()
+        } else if *self.action {
+            self.time.width() + self.marker.width() + "* ".len() + self.sender.name.width() + 1
+        } else if *self.continued {
+            self.indent.width()
+        } else {
+            self.time.width() + self.marker.width() + self.sender.name.width() + 1
+        };
+        let width = prefix + self.data.width() + self.repeat.width();
+
+        let shown = if truncate && width > columns {
+            let room = columns.saturating_sub(prefix + self.repeat.width() + 1);
+            let mut used = 0;
+            let mut shown = self
+                .data
+                .chars()
+                .take_while(|ch| {
+                    used += ch.width().unwrap_or(0);
+                    used <= room
+                })
+                .collect::<String>();
+            shown.push('…');
+            shown
+        } else {
+            self.data.to_string()
+        };
+
+        let (shown, mention, after) = match *self.is_system {
+            true => (shown, String::new(), String::new()),
+            false => split_mention(shown, our_name),
+        };
+        if *self.shown != shown {
+            *self.shown = shown;
+        }
+        if *self.mention != mention {
+            *self.mention = mention;
+        }
+        if *self.shown_after != after {
+            *self.shown_after = after;
+        }
+
+        match truncate {
+            true => 1,
+            false => width.div_ceil(columns).max(1),
+        }
+    }
+
+    pub fn format_time(&mut self, format: &TimestampFormat) {
+        let time = match format.hidden {
+            true => String::new(),
+            false => format!("{} ", format.format_millis(*self.timestamp)),
+        };
+        if *self.time != time {
+            *self.time = time;
+        }
+    }
 }
 
 impl From<twitch::Message> for AnaMessage {
@@ -15,10 +187,77 @@ impl From<twitch::Message> for AnaMessage {
             sender: StateValue::new(value.sender.into()),
             channel: StateValue::new(value.channel),
             data: StateValue::new(value.data),
+            shown: StateValue::default(),
+            mention: StateValue::default(),
+            shown_after: StateValue::default(),
+            text_color: StateValue::new(anathema::core::Color::Reset),
+            timestamp: StateValue::new(value.timestamp.timestamp_millis()),
+            time: StateValue::default(),
+            is_system: StateValue::new(false),
+            action: StateValue::new(value.action),
+            marker: StateValue::default(),
+            visible: StateValue::new(true),
+            continued: StateValue::new(false),
+            indent: StateValue::default(),
+            count: StateValue::new(1),
+            repeat: StateValue::default(),
+            id: StateValue::new(value.id.unwrap_or_default()),
         }
     }
 }
 
+/// A color for the most significant of `roles`, used instead of the user's own color
+///
+/// `None` if they have no role, they keep their own color then
+pub const fn role_color(roles: twitch::Roles) -> Option<anathema::core::Color> {
+    use anathema::core::Color;
+    let color = if roles.broadcaster {
+        Color::Rgb {
+            r: 233,
+            g: 25,
+            b: 22,
+        }
+    } else if roles.moderator {
+        Color::Rgb { r: 0, g: 173, b: 3 }
+    } else if roles.vip {
+        Color::Rgb {
+            r: 224,
+            g: 5,
+            b: 185,
+        }
+    } else if roles.subscriber {
+        Color::Rgb {
+            r: 100,
+            g: 65,
+            b: 165,
+        }
+    } else {
+        return None;
+    };
+    Some(color)
+}
+
+/// Splits `data` around the first mention of `name` (and an `@` before it), ignoring case
+fn split_mention(mut data: String, name: &str) -> (String, String, String) {
+    if name.is_empty() {
+        return (data, String::new(), String::new());
+    }
+
+    // twitch names are ascii, so lowercasing keeps the byte offsets the same
+    let found = data.to_ascii_lowercase().find(&name.to_ascii_lowercase());
+    let Some(mut start) = found else {
+        return (data, String::new(), String::new());
+    };
+
+    let end = start + name.len();
+    if data[..start].ends_with('@') {
+        start -= 1;
+    }
+    let after = data.split_off(end);
+    let mention = data.split_off(start);
+    (data, mention, after)
+}
+
 const fn map_color(color: twitch_message::Color) -> anathema::core::Color {
     let twitch_message::Color(r, g, b) = color;
     anathema::core::Color::Rgb { r, g, b }
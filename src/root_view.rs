@@ -1,33 +1,1566 @@
 use anathema::{
-    core::KeyModifiers,
+    core::{KeyCode, KeyModifiers, MouseButton},
     values::{List, StateValue},
 };
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
 use smol::channel::{Receiver, Sender};
 
-use crate::{display_channel::DisplayChannel, geometry::pos2, model, tab, tabs::Tabs, twitch};
+use crate::{
+    channel::{is_channel, is_valid_name, SendCheck},
+    completion::Completion,
+    config::{Config, DuplicateMessages, LongMessages, Notify},
+    connection_state::ConnectionState,
+    display_channel::DisplayChannel,
+    geometry::pos2,
+    history::{History, Search},
+    input::Cursor,
+    model,
+    session::Session,
+    switcher::{Switcher, SwitcherItem},
+    tab,
+    tabs::Tabs,
+    twitch,
+};
 
 #[derive(Debug, Default, anathema::values::State)]
 pub struct RootState {
-    pub status: StateValue<String>,
+    pub status: StateValue<ConnectionState>,
+    /// Lights up when something is read from the server, so a quiet connection can be told from a dead one
+    pub heartbeat: StateValue<String>,
+    /// e.g. ` | send queue busy (3)`, while the connection isn't keeping up with our requests
+    pub send_busy: StateValue<String>,
+    /// e.g. `(network error, attempt 3, retrying in 8s) `, empty until we know when we're reconnecting
+    pub reconnect_status: StateValue<String>,
     pub our_user: StateValue<model::AnaUser>,
     pub input: StateValue<String>,
+    /// The input split around the cursor, so it can be drawn over the grapheme it's on
+    pub input_before: StateValue<String>,
+    pub input_under: StateValue<String>,
+    pub input_after: StateValue<String>,
+    /// The cursor glyph, when it's at the end of the input
+    pub cursor_end: StateValue<String>,
+    pub cursor_color: StateValue<anathema::core::Color>,
+    /// e.g. `joined on connect: #foo, #bar`, empty if no channels are joined on connect
+    pub auto_join: StateValue<String>,
+    pub search_prompt: StateValue<String>,
+    pub cooldown: StateValue<String>,
+    /// e.g. `12/20 sends `, or `next send in 8s ` once they're used up, empty while none are used
+    pub rate_limit: StateValue<String>,
+    pub completions: StateValue<String>,
+    pub unseen: StateValue<String>,
+    pub active_channel: StateValue<String>,
+    /// Set while a whisper tab is active, as replies can't be sent from it
+    pub read_only: StateValue<bool>,
+    pub room_modes: StateValue<String>,
+    pub tabs_before: StateValue<String>,
+    pub tabs_after: StateValue<String>,
     pub channels: List<DisplayChannel>,
     pub output: List<model::AnaMessage>,
+    pub sidebar_open: StateValue<bool>,
+    pub sidebar_width: StateValue<usize>,
+    /// The keys of the view's tab and sidebar regions, for the template's `tab` widgets
+    pub tab_regions: StateValue<String>,
+    pub sidebar_regions: StateValue<String>,
+    pub switcher_open: StateValue<bool>,
+    pub switcher_query: StateValue<String>,
+    pub switcher_items: List<SwitcherItem>,
 }
 
 pub struct RootView {
     pub state: RootState,
     pub tabs: Tabs,
+    pub config: Config,
+    pub session: Session,
+    /// The session as of the last tick, saved by `main` once the runtime has stopped
+    exit_session: Arc<Mutex<Session>>,
     pub feed: Receiver<twitch::Response>,
     pub send: Sender<twitch::Request>,
+    /// Where the tabs were drawn, shared with the `tab` widgets
+    regions: tab::TabRegions,
+    /// Where the sidebar entries were drawn, shared with the `tab` widgets in the sidebar
+    sidebar_regions: tab::TabRegions,
+    /// The labels the sidebar listed last, its entries moved if they changed
+    sidebar_labels: Vec<String>,
+    width: u16,
+    height: u16,
+    /// Channels whose tabs were closed while we're still joined to them
+    closed: HashSet<String>,
+    /// Channels we asked to join, and haven't heard back about
+    joining: HashSet<String>,
+    /// Channels the server confirmed we joined, since we last connected
+    joined: HashSet<String>,
+    /// When the visible timestamps were last formatted, relative ones go stale
+    last_time_refresh: Instant,
+    /// The channel that was active when we were disconnected (or quit last time), and when to give up on it
+    restore_active: Option<(String, Option<Instant>)>,
+    /// The channels joined on startup, and when to stop waiting for them, until they're summarized
+    startup_joins: Option<(Vec<String>, Option<Instant>)>,
+    completion: Option<Completion>,
+    history: History,
+    cursor: Cursor,
+    /// The reverse history search, while one is in progress
+    search: Option<Search>,
+    /// Whether the terminal has focus, `None` until it tells us
+    focused: Option<bool>,
+    /// Whether we lost the connection since we last connected
+    disconnected: bool,
+    /// When the user last did something
+    last_input: Instant,
+    /// Whether we disconnected because the user was idle
+    idle: bool,
+    /// When we last heard from the server
+    last_heartbeat: Option<Instant>,
+    /// Which reconnect attempt we're waiting on, and when it'll be made
+    reconnect_at: Option<(u32, Instant)>,
+    /// Why we were last disconnected, until we've reconnected
+    disconnect_reason: Option<twitch::DisconnectReason>,
+    /// Messages sent while disconnected, and when
+    offline_queue: VecDeque<(twitch::Request, Instant)>,
+    /// Requests the connection couldn't take yet, oldest first
+    blocked_requests: VecDeque<twitch::Request>,
+    /// How many messages we've sent recently, as of the last one
+    rate_limit: twitch::RateLimit,
+    /// When ctrl-c was pressed, if it's waiting to be pressed again to quit
+    quit_requested: Option<Instant>,
+    /// The matches of the last `/search`, oldest first, `/jump` numbers them from 1
+    search_results: Vec<SearchResult>,
+    /// The fuzzy channel switcher, while it's open
+    switcher: Option<Switcher>,
 }
 
 impl RootView {
-    const CONNECTING: &'static str = "connecting";
-    const CONNECTED: &'static str = "connected";
-    const RECONNECTING: &'static str = "reconnecting";
-    const INVALID_AUTH: &'static str = "invalid_auth";
-    const ON_NO_CHANNELS: &'static str = "on_no_channels";
+    pub fn new(
+        mut config: Config,
+        session: Session,
+        exit_session: Arc<Mutex<Session>>,
+        auto_join: &[String],
+        regions: tab::TabRegions,
+        sidebar_regions: tab::TabRegions,
+        feed: Receiver<twitch::Response>,
+        send: Sender<twitch::Request>,
+    ) -> Self {
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        if let Some(show) = session.timestamps {
+            config.timestamp.hidden = !show;
+        }
+        let tabs = Tabs {
+            order: session.channels.clone(),
+            palette: config.palette,
+            ..Tabs::default()
+        };
+
+        let state = RootState {
+            cursor_color: StateValue::new(config.cursor_color),
+            sidebar_width: StateValue::new(Self::SIDEBAR_WIDTH),
+            tab_regions: StateValue::new(regions.key().to_string()),
+            sidebar_regions: StateValue::new(sidebar_regions.key().to_string()),
+            auto_join: StateValue::new(match auto_join {
+                [] => String::new(),
+                channels => format!("joined on connect: {}", channels.join(", ")),
+            }),
+            ..RootState::default()
+        };
+
+        Self {
+            state,
+            tabs,
+            config,
+            session,
+            exit_session,
+            feed,
+            send,
+            regions,
+            sidebar_regions,
+            sidebar_labels: Vec::new(),
+            width,
+            height,
+            closed: HashSet::new(),
+            joining: HashSet::new(),
+            joined: HashSet::new(),
+            restore_active: session.active.clone().map(|channel| (channel, None)),
+            startup_joins: (!auto_join.is_empty()).then(|| (auto_join.to_vec(), None)),
+            last_time_refresh: Instant::now(),
+            completion: None,
+            history: History::default(),
+            cursor: Cursor::default(),
+            search: None,
+            focused: None,
+            disconnected: false,
+            last_input: Instant::now(),
+            idle: false,
+            last_heartbeat: None,
+            reconnect_at: None,
+            disconnect_reason: None,
+            offline_queue: VecDeque::new(),
+            blocked_requests: VecDeque::new(),
+            rate_limit: twitch::RateLimit::default(),
+            quit_requested: None,
+            search_results: Vec::new(),
+            switcher: None,
+        }
+    }
+
+    fn update_tab_scroll(&mut self) {
+        const BEFORE: &str = "‹ ";
+        const AFTER: &str = " ›";
+
+        Tabs::update_numbers(&mut self.state.channels, self.config.tab_numbers);
+
+        // every entry after an added, removed or renamed one moved
+        let labels = self
+            .tabs
+            .channels
+            .iter()
+            .map(|channel| channel.label().to_string())
+            .collect::<Vec<_>>();
+        if labels != self.sidebar_labels {
+            self.sidebar_regions.clear();
+            self.sidebar_labels = labels;
+        }
+
+        let scroll = self.tabs.scroll;
+        let width =
+            (self.width as usize).saturating_sub(BEFORE.chars().count() + AFTER.chars().count());
+        let (before, after) = self.tabs.update_scroll(&mut self.state.channels, width);
+
+        if scroll != self.tabs.scroll {
+            // hidden tabs would otherwise keep their old regions
+            self.regions.clear();
+        }
+
+        let before = if before { BEFORE } else { "" };
+        if *self.state.tabs_before != before {
+            *self.state.tabs_before = before.to_string();
+        }
+
+        let after = if after { AFTER } else { "" };
+        if *self.state.tabs_after != after {
+            *self.state.tabs_after = after.to_string();
+        }
+    }
+
+    /// Shows a client-generated line in the active channel
+    fn push_system_message(&mut self, data: impl ToString) {
+        let channel = self
+            .tabs
+            .active()
+            .map(|c| c.name.clone())
+            .unwrap_or_default();
+        let mut msg = model::AnaMessage::system(channel, data);
+        msg.format_time(&self.config.timestamp);
+        self.push_output(msg);
+    }
+
+    /// Adds a message to the active channel, keeping our place if we're scrolled up
+    fn push_output(&mut self, msg: model::AnaMessage) {
+        if let Some(active) = self.tabs.active_mut() {
+            active.keep_scroll();
+        }
+        self.state.output.push_back(msg);
+        while self.state.output.len() > self.config.scrollback {
+            self.state.output.pop_front();
+        }
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        if let Some(active) = self.tabs.active_mut() {
+            active.scroll_by(delta);
+        }
+    }
+
+    /// How many rows are left for messages, after the tabs, status bar and input
+    fn message_rows(&self) -> usize {
+        let marker = self.tabs.active().is_some_and(|c| c.unseen > 0) as usize;
+        (self.height as usize).saturating_sub(3 + marker).max(1)
+    }
+
+    /// Shows the messages that fit on screen, ending the active channel's `scroll` messages above the newest one
+    fn update_visible_messages(&mut self) {
+        let len = self.state.output.len();
+        let rows = self.message_rows();
+        let (scroll, unseen) = match self.tabs.active_mut() {
+            Some(active) => {
+                active.scroll = active.scroll.min(len.saturating_sub(rows));
+                if active.scroll == 0 {
+                    active.unseen = 0;
+                }
+                (active.scroll, active.unseen)
+            }
+            None => (0, 0),
+        };
+
+        let columns = match *self.state.sidebar_open {
+            true => (self.width as usize).saturating_sub(Self::SIDEBAR_WIDTH),
+            false => self.width as usize,
+        };
+        let truncate = self.config.long_messages == LongMessages::Truncate;
+        let our_name = self.state.our_user.name.to_string();
+
+        // long messages take several rows, but the newest one is shown even if it doesn't fit
+        let end = len - scroll;
+        let (mut start, mut used) = (end, 0);
+        while start > 0 {
+            let needed = self.state.output[start - 1].fit(columns, truncate, &our_name);
+            if used + needed > rows && start < end {
+                break;
+            }
+            used += needed;
+            start -= 1;
+        }
+
+        for i in 0..len {
+            let visible = (start..end).contains(&i);
+            if *self.state.output[i].visible != visible {
+                *self.state.output[i].visible = visible;
+            }
+        }
+
+        if self.config.group_messages {
+            // the top line always has a name, even if the one scrolled past it was from them too
+            for i in start..end {
+                let continued =
+                    i > start && self.state.output[i].continues(&self.state.output[i - 1]);
+                self.state.output[i].set_continued(continued);
+            }
+        }
+
+        // the newest few lines stay bright, counting from the bottom of the view
+        for i in start..end {
+            let faded = self.config.fade_older && end - i > Self::UNFADED_LINES;
+            self.state.output[i].set_faded(faded);
+        }
+
+        let unseen = match unseen {
+            0 => String::new(),
+            n => format!("▼ {n} new"),
+        };
+        if *self.state.unseen != unseen {
+            *self.state.unseen = unseen;
+        }
+    }
+
+    /// Shows a client-generated line in `channel`, or the active channel if it has no tab
+    fn push_system_message_to(&mut self, channel: &str, data: impl ToString) {
+        let index = self
+            .tabs
+            .find_index_by_name(channel)
+            .filter(|&index| index != self.tabs.active);
+
+        let Some(index) = index else {
+            return self.push_system_message(data);
+        };
+
+        let mut msg = model::AnaMessage::system(channel, data);
+        msg.format_time(&self.config.timestamp);
+        self.tabs.channels[index].push_message(msg, self.config.scrollback);
+    }
+
+    /// Counts `msg` on the last line of the channel at `index` instead, if it repeats that line
+    fn collapse_repeat(&mut self, index: Option<usize>, msg: &model::AnaMessage) -> bool {
+        if !self.config.collapse_repeats {
+            return false;
+        }
+
+        // a channel without a tab has nothing to repeat, the active output belongs to another one
+        let Some(index) = index else {
+            return false;
+        };
+        let last = match index == self.tabs.active {
+            false => self.tabs.channels[index].messages.back_mut(),
+            true => match self.state.output.len() {
+                0 => None,
+                len => Some(&mut self.state.output[len - 1]),
+            },
+        };
+
+        match last {
+            Some(last) if last.repeats(msg) => {
+                last.add_repeat();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Shows a client-generated line in every open channel
+    fn push_system_message_to_all(&mut self, data: impl ToString) {
+        let data = data.to_string();
+        let names = self
+            .tabs
+            .channels
+            .iter()
+            .map(|channel| channel.name.clone())
+            .collect::<Vec<_>>();
+        for name in names {
+            self.push_system_message_to(&name, &data);
+        }
+    }
+
+    /// Sends each non-blank line of `data` as its own message, in order and without trailing whitespace
+    fn send_lines(&mut self, data: String) {
+        // twitch drops blank messages, so there's no point in sending them (or waiting on their echo)
+        let mut lines = data
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.trim_start().is_empty());
+        while let Some(line) = lines.next() {
+            if !self.send_message(line.to_string()) {
+                // keep what wasn't sent, so it can be sent later
+                *self.state.input = std::iter::once(line)
+                    .chain(lines)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return;
+            }
+        }
+    }
+
+    /// Sends `data` to the active channel, unless the room would reject it
+    ///
+    /// Returns whether it was sent, if not the input holds it again
+    fn send_message(&mut self, mut data: String) -> bool {
+        let Some(active) = self.tabs.active_mut() else {
+            return false;
+        };
+
+        if active.name.starts_with('@') {
+            *self.state.input = data;
+            self.push_system_message(Self::WHISPERS_UNSUPPORTED);
+            return false;
+        }
+
+        if !active.is_channel() {
+            *self.state.input = data;
+            self.push_system_message("messages can't be sent from this tab");
+            return false;
+        }
+
+        if !self.check_sendable(&data) {
+            return false;
+        }
+        let Some(active) = self.tabs.active_mut() else {
+            return false;
+        };
+
+        if active.is_duplicate(&data) {
+            match self.config.duplicate_messages {
+                DuplicateMessages::Bypass => data.push_str(Self::DUPLICATE_SUFFIX),
+                DuplicateMessages::Warn => {
+                    *self.state.input = data;
+                    self.push_system_message(
+                        "twitch drops identical messages sent within 30 seconds",
+                    );
+                    return false;
+                }
+            }
+        }
+
+        let check = active.check_restrictions();
+        let sent = !matches!(check, SendCheck::Denied(..));
+        if sent {
+            let channel = active.name.clone();
+            self.send_chat(twitch::Request::SendMesage { channel, data });
+        } else {
+            *self.state.input = data;
+        }
+
+        if let SendCheck::Denied(msg) | SendCheck::Unsure(msg) = check {
+            self.push_system_message(msg);
+        }
+        sent
+    }
+
+    /// Checks what anything sent to the active channel has to pass, chat commands included
+    ///
+    /// If `data` can't be sent, the input holds it again and a line says why
+    fn check_sendable(&mut self, data: &str) -> bool {
+        let Some(active) = self.tabs.active() else {
+            return false;
+        };
+
+        // while disconnected it's held until we've re-joined
+        let msg = if !self.disconnected && !self.joined.contains(&active.name) {
+            format!("not joined to {}, the message wasn't sent", active.name)
+        } else if let Some(remaining) = active
+            .slow_mode_remaining()
+            .filter(|_| self.config.slow_mode_block)
+        {
+            // keep the input around so it can be sent once the cooldown ends
+            format!(
+                "slow mode, wait {}s before sending again",
+                remaining.as_secs_f32().ceil()
+            )
+        } else if let Some(wait) = self.rate_limit.next_available(self.send_capacity()) {
+            // going over it gets us locked out of chat, which is worse than waiting
+            format!(
+                "sending too fast, wait {}s before sending again",
+                wait.as_millis().div_ceil(1000)
+            )
+        } else {
+            return true;
+        };
+
+        *self.state.input = data.to_string();
+        self.push_system_message(msg);
+        false
+    }
+
+    /// Saves the session and disconnects, the returned event stops the runtime
+    fn quit(&mut self) -> anathema::core::Event {
+        self.save_session();
+        // we're stopping either way, there's nothing to retry
+        let _ = self
+            .send
+            .try_send(twitch::Request::Disconnect { reconnect: false });
+
+        anathema::core::Event::Stop
+    }
+
+    /// Hands `request` to the connection without blocking, holding on to it if the channel is full
+    fn send_request(&mut self, request: twitch::Request) {
+        if let twitch::Request::SendMesage { channel, data } = &request {
+            // the connection counts it too, but several lines can be sent before it tells us so
+            self.rate_limit.record();
+            // slow mode counts from when it's sent, not when it was typed
+            if let Some(index) = self.tabs.find_index_by_name(channel) {
+                self.tabs.channels[index].mark_sent(data);
+            }
+        }
+
+        // keep them in order behind anything that's already waiting
+        if !self.blocked_requests.is_empty() {
+            self.blocked_requests.push_back(request);
+            return;
+        }
+
+        if let Err(smol::channel::TrySendError::Full(request)) = self.send.try_send(request) {
+            self.blocked_requests.push_back(request);
+        }
+    }
+
+    /// Retries the requests that didn't fit, on every tick until the connection catches up
+    fn flush_blocked_requests(&mut self) {
+        while let Some(request) = self.blocked_requests.pop_front() {
+            match self.send.try_send(request) {
+                Ok(()) => {}
+                Err(smol::channel::TrySendError::Full(request)) => {
+                    self.blocked_requests.push_front(request);
+                    break;
+                }
+                // the connection is gone, nothing will read them
+                Err(smol::channel::TrySendError::Closed(..)) => self.blocked_requests.clear(),
+            }
+        }
+
+        let busy = match self.blocked_requests.len() {
+            0 => String::new(),
+            len => format!(" | send queue busy ({len})"),
+        };
+        if *self.state.send_busy != busy {
+            *self.state.send_busy = busy;
+        }
+    }
+
+    /// Sends a chat message, or holds on to it until we've reconnected and it can go out
+    ///
+    /// Held messages wait in order behind each other, on the slow mode of their channel and on the
+    /// rate limit, like typed messages do
+    fn send_chat(&mut self, request: twitch::Request) {
+        if !self.disconnected && self.offline_queue.is_empty() && self.send_wait(&request).is_none()
+        {
+            self.send_request(request);
+            return;
+        }
+
+        if self.disconnected && self.offline_queue.is_empty() {
+            self.push_system_message("not connected, messages will be sent once we've reconnected");
+        }
+        self.offline_queue.push_back((request, Instant::now()));
+    }
+
+    /// How long until `request` can be sent, by its channel's slow mode and our rate limit
+    fn send_wait(&self, request: &twitch::Request) -> Option<Duration> {
+        let twitch::Request::SendMesage { channel, .. } = request else {
+            return None;
+        };
+        let channel = self.tabs.channel(channel)?;
+        let slow = channel
+            .slow_mode_remaining()
+            .filter(|_| self.config.slow_mode_block);
+        let capacity = twitch::RateLimit::capacity(channel.our_roles.as_ref());
+        slow.max(self.rate_limit.next_available(capacity))
+    }
+
+    /// Sends what was held while disconnected once we're back and it can go out, dropping what waited
+    /// too long for the reconnect
+    fn drain_offline_queue(&mut self) {
+        while let Some((request, queued)) = self.offline_queue.front() {
+            if !self.disconnected {
+                // a burst of them could get us locked out of chat, so they're spaced out
+                if self.send_wait(request).is_some() {
+                    break;
+                }
+                let Some((request, _)) = self.offline_queue.pop_front() else {
+                    break;
+                };
+                self.send_request(request);
+                continue;
+            }
+
+            if queued.elapsed() < Self::OFFLINE_QUEUE_TIMEOUT {
+                break;
+            }
+            let Some((request, _)) = self.offline_queue.pop_front() else {
+                break;
+            };
+
+            let msg = match request {
+                twitch::Request::SendMesage { channel, data } => format!("{channel}: {data}"),
+                _ => continue,
+            };
+            self.push_system_message(format!("couldn't reconnect in time, dropped: {msg}"));
+        }
+    }
+
+    /// Completes the last word of the input, or cycles to the next (or previous) candidate
+    fn complete(&mut self, forward: bool) {
+        if !matches!(&self.completion, Some(c) if c.is_current(&self.state.input)) {
+            let commands = command_names();
+            let names = self
+                .tabs
+                .active()
+                .into_iter()
+                .flat_map(|channel| channel.recent_chatters().rev());
+            self.completion = Completion::new(&self.state.input, commands, names);
+        }
+
+        let Some(completion) = &mut self.completion else {
+            return;
+        };
+        *self.state.input = completion.cycle(forward).to_string();
+        *self.state.completions = completion.display();
+    }
+
+    /// Splits the input around the cursor for drawing
+    fn update_input_view(&mut self) {
+        let prompt = self.state.search_prompt.chars().count();
+        let columns = (self.width as usize).saturating_sub(prompt + 1);
+        let (before, under, after) = self.cursor.split(&self.state.input, columns);
+        let cursor_end = if under.is_empty() {
+            &*self.config.cursor
+        } else {
+            ""
+        };
+
+        for (value, new) in [
+            (&mut self.state.input_before, before),
+            (&mut self.state.input_under, under),
+            (&mut self.state.input_after, after),
+            (&mut self.state.cursor_end, cursor_end),
+        ] {
+            if **value != new {
+                **value = new.to_string();
+            }
+        }
+    }
+
+    /// Forgets the completion once the input was edited some other way
+    fn update_completion(&mut self) {
+        if matches!(&self.completion, Some(c) if !c.is_current(&self.state.input)) {
+            self.completion.take();
+            self.state.completions.clear();
+        }
+    }
+
+    fn start_search(&mut self) {
+        self.search = Some(Search {
+            original: std::mem::take(&mut *self.state.input),
+            ..Search::default()
+        });
+        self.update_search();
+    }
+
+    /// Handles a key press while reverse-searching the history
+    fn search_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        match code {
+            KeyCode::Char('r') if modifiers == KeyModifiers::CONTROL => {
+                // step to an older match, staying on this one if there isn't one
+                search.index = self
+                    .history
+                    .find(&search.query, search.index)
+                    .or(search.index);
+            }
+
+            KeyCode::Char(c) if modifiers != KeyModifiers::CONTROL => {
+                search.query.push(c);
+                // the current match might still match, so include it
+                let before = search.index.map(|index| index + 1);
+                search.index = self.history.find(&search.query, before);
+            }
+
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.index = self.history.find(&search.query, None);
+            }
+
+            KeyCode::Enter | KeyCode::Esc => {
+                let search = self.search.take().unwrap_or_default();
+                *self.state.input = search
+                    .index
+                    .filter(|_| code == KeyCode::Enter)
+                    .and_then(|index| self.history.get(index))
+                    .map_or(search.original, ToString::to_string);
+            }
+
+            _ => {}
+        }
+
+        self.update_search();
+    }
+
+    /// Shows the current match in the input, with the search query before it
+    fn update_search(&mut self) {
+        let Some(search) = &self.search else {
+            self.state.search_prompt.clear();
+            return;
+        };
+
+        let found = search.index.and_then(|index| self.history.get(index));
+        *self.state.input = found.unwrap_or_default().to_string();
+        *self.state.search_prompt = match found {
+            Some(..) => format!("search `{}`: ", search.query),
+            None => format!("failed search `{}`: ", search.query),
+        };
+    }
+
+    /// Rings the terminal bell, unless we know the user is looking at it
+    fn ring_bell(&self) {
+        if self.focused == Some(true) {
+            return;
+        }
+
+        use std::io::Write as _;
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+    }
+
+    fn is_mention(&self, data: &str) -> bool {
+        let name = &*self.state.our_user.name;
+        !name.is_empty() && data.to_lowercase().contains(&name.to_lowercase())
+    }
+
+    /// Shows how many joined and left each channel, once per configured window
+    fn summarize_membership(&mut self) {
+        let Some(window) = self.config.membership_summary else {
+            return;
+        };
+
+        let summaries = self
+            .tabs
+            .channels
+            .iter_mut()
+            .filter_map(|channel| {
+                let (joins, parts) = channel.take_membership(window)?;
+                Some((channel.name.clone(), joins, parts))
+            })
+            .collect::<Vec<_>>();
+
+        let period = match window.as_secs() {
+            60 => String::from("minute"),
+            secs if secs % 60 == 0 => format!("{} minutes", secs / 60),
+            secs => format!("{secs} seconds"),
+        };
+
+        for (channel, joins, parts) in summaries {
+            let summary = match (joins, parts) {
+                (joins, 0) => format!("{joins} joined in the last {period}"),
+                (0, parts) => format!("{parts} left in the last {period}"),
+                (joins, parts) => format!("{joins} joined, {parts} left in the last {period}"),
+            };
+            self.push_system_message_to(&channel, summary);
+        }
+    }
+
+    /// Disconnects once there's been no input for the configured time
+    fn check_idle(&mut self) {
+        let Some(timeout) = self.config.idle_disconnect else {
+            return;
+        };
+
+        if !self.idle && !self.disconnected && self.last_input.elapsed() >= timeout {
+            self.idle = true;
+            self.send_request(twitch::Request::Pause);
+        }
+    }
+
+    /// Counts down to the next reconnect attempt
+    fn update_reconnect_status(&mut self) {
+        let status = match self.reconnect_at {
+            Some((attempt, at)) => {
+                // round up, so it doesn't say `0s` while there's still time left
+                let left = at.saturating_duration_since(Instant::now());
+                let secs = left.as_millis().div_ceil(1000);
+                match &self.disconnect_reason {
+                    Some(reason) => format!("({reason}, attempt {attempt}, retrying in {secs}s) "),
+                    None => format!("(attempt {attempt}, retrying in {secs}s) "),
+                }
+            }
+            None => String::new(),
+        };
+        if *self.state.reconnect_status != status {
+            *self.state.reconnect_status = status;
+        }
+    }
+
+    fn update_connected_status(&mut self) {
+        *self.state.status = if self.state.channels.is_empty() {
+            ConnectionState::OnNoChannels
+        } else {
+            ConnectionState::Connected
+        };
+    }
+
+    fn save_session(&mut self) {
+        self.update_session();
+        let Some(path) = &self.config.session else {
+            return;
+        };
+        if let Err(err) = self.session.save(path) {
+            self.push_system_message(format!("cannot save the session: {err}"));
+        }
+    }
+
+    /// Brings the session up to date with the tabs, without saving it
+    fn update_session(&mut self) {
+        // pseudo-tabs aren't channels we can join
+        self.session.channels = self
+            .tabs
+            .order
+            .iter()
+            .filter(|channel| is_channel(channel))
+            .cloned()
+            .collect();
+
+        // channels we haven't re-joined yet keep what they had
+        let mut unread = std::collections::BTreeMap::new();
+        for channel in &self.session.channels {
+            let counts = match self.tabs.find_index_by_name(channel) {
+                Some(index) => self.state.channels[index].unread_counts(),
+                None => self
+                    .session
+                    .unread
+                    .get(channel)
+                    .copied()
+                    .unwrap_or_default(),
+            };
+            if counts.0 > 0 {
+                unread.insert(channel.clone(), counts);
+            }
+        }
+        self.session.unread = unread;
+
+        // until it's restored, the channel we're waiting on is still the one to come back to
+        if self.restore_active.is_none() {
+            self.session.active = self
+                .tabs
+                .active()
+                .filter(|channel| channel.is_channel())
+                .map(|channel| channel.name.clone());
+        }
+
+        let channels = &self.session.channels;
+        self.session
+            .aliases
+            .retain(|channel, _| channels.contains(channel));
+        self.session
+            .notify
+            .retain(|channel, _| channels.contains(channel));
+    }
+
+    /// Leaves `channel`, pseudo-tabs are just removed as there's nothing to leave
+    fn leave_channel(&mut self, channel: &str) {
+        if is_channel(channel) {
+            self.send_request(twitch::Request::PartChannel {
+                channel: channel.to_string(),
+            });
+            return;
+        }
+
+        self.closed.remove(channel);
+        self.tabs.part_channel(channel, &mut self.state);
+        self.tabs.order.retain(|c| c != channel);
+        self.update_connected_status();
+    }
+
+    /// The channel whose sidebar entry was drawn at `x, y`
+    fn sidebar_channel_at(&self, x: u16, y: u16) -> Option<usize> {
+        if !*self.state.sidebar_open {
+            return None;
+        }
+        let label = self.sidebar_regions.containing_point(pos2(x, y))?;
+        self.tabs.find_index_by_label(&label)
+    }
+
+    /// Marks the message with `id` in `channel` as deleted
+    fn clear_message(&mut self, channel: &str, id: &str) {
+        if self.tabs.active_name() == Some(channel) {
+            let output = &mut self.state.output;
+            if let Some(index) = (0..output.len()).rposition(|i| output[i].id() == Some(id)) {
+                output[index].delete();
+                self.update_visible_messages();
+            }
+            return;
+        }
+
+        let Some(index) = self.tabs.find_index_by_name(channel) else {
+            return;
+        };
+        let messages = &mut self.tabs.channels[index].messages;
+        if let Some(msg) = messages.iter_mut().rev().find(|msg| msg.id() == Some(id)) {
+            msg.delete();
+        }
+    }
+
+    /// Shows `alias` on the tab for `channel` instead of its name, or its name again with `None`
+    fn apply_alias(&mut self, channel: &str, alias: Option<String>) {
+        let Some(index) = self.tabs.find_index_by_name(channel) else {
+            return;
+        };
+
+        let label = alias.clone().unwrap_or_else(|| channel.to_string());
+        self.tabs.channels[index].alias = alias;
+        *self.state.channels[index].label = label;
+        // the tab changed width, so every tab after it moved
+        self.regions.clear();
+    }
+
+    /// Applies the session's alias and notification setting for `channel` to its new tab
+    fn apply_channel_settings(&mut self, channel: &str) {
+        if let Some(alias) = self.session.aliases.get(channel).cloned() {
+            self.apply_alias(channel, Some(alias));
+        }
+        if let Some(index) = self.tabs.find_index_by_name(channel) {
+            self.tabs.channels[index].notify = self.session.notify.get(channel).copied();
+        }
+    }
+
+    /// Sets which messages ring the bell in the active channel, or shows it without `notify`
+    fn set_notify(&mut self, notify: Option<Notify>) {
+        let default = self.config.notify;
+        let Some(active) = self.tabs.active_mut() else {
+            return;
+        };
+
+        let Some(notify) = notify else {
+            let msg = match active.notify {
+                Some(notify) => format!("{} notifies on: {}", active.name, notify.as_str()),
+                None => format!(
+                    "{} notifies on: {} (default)",
+                    active.name,
+                    default.as_str()
+                ),
+            };
+            return self.push_system_message(msg);
+        };
+
+        active.notify = Some(notify);
+        let msg = format!("{} notifies on: {}", active.name, notify.as_str());
+        if active.is_channel() {
+            self.session.notify.insert(active.name.clone(), notify);
+            self.save_session();
+        }
+        self.push_system_message(msg);
+    }
+
+    /// Sets (or with `None`, clears) the alias of the active channel
+    fn set_alias(&mut self, alias: Option<&str>) {
+        let Some(channel) = self.tabs.active_name().map(ToString::to_string) else {
+            return;
+        };
+
+        let alias = alias.map(str::trim).filter(|alias| !alias.is_empty());
+
+        // tabs are found by what they show, so that has to be unique
+        if let Some(alias) = alias {
+            let taken = self
+                .tabs
+                .order
+                .iter()
+                .chain(self.tabs.channels.iter().map(|channel| &channel.name))
+                .chain(
+                    self.session
+                        .aliases
+                        .iter()
+                        .filter_map(|(other, alias)| (*other != channel).then_some(alias)),
+                )
+                .any(|name| *name != channel && name.eq_ignore_ascii_case(alias));
+            if taken {
+                let msg = format!("{alias} is already the name or alias of another tab");
+                return self.push_system_message(msg);
+            }
+        }
+
+        match alias {
+            Some(alias) => {
+                self.session
+                    .aliases
+                    .insert(channel.clone(), alias.to_string());
+            }
+            None => {
+                self.session.aliases.remove(&channel);
+            }
+        }
+
+        self.apply_alias(&channel, alias.map(ToString::to_string));
+        self.save_session();
+    }
+
+    /// Lists the messages in every channel containing `query`, ignoring case
+    fn search_all(&mut self, query: &str) {
+        let lowercase = query.to_lowercase();
+        let mut results = vec![];
+        for channel in &self.tabs.channels {
+            let messages = self
+                .tabs
+                .messages(&channel.name, list_items(&self.state.output))
+                .unwrap_or_default();
+            results.extend(
+                messages
+                    .into_iter()
+                    .filter(|msg| !*msg.is_system && msg.data.to_lowercase().contains(&lowercase))
+                    .map(|msg| SearchResult {
+                        channel: channel.name.clone(),
+                        timestamp: *msg.timestamp,
+                        sender: msg.sender.name.to_string(),
+                        data: msg.data.to_string(),
+                        time: msg.time.to_string(),
+                    }),
+            );
+        }
+
+        results.sort_by_key(|result| result.timestamp);
+        let skip = results.len().saturating_sub(Self::MAX_SEARCH_RESULTS);
+        self.search_results = results.split_off(skip);
+
+        if self.search_results.is_empty() {
+            return self.push_system_message(format!("no messages match `{query}`"));
+        }
+
+        let lines = self
+            .search_results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                format!(
+                    "{}. {} {}{}: {}",
+                    i + 1,
+                    result.channel,
+                    result.time,
+                    result.sender,
+                    result.data
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let count = match skip {
+            0 => format!("{} matches", lines.len()),
+            _ => format!(
+                "the newest {} of {} matches",
+                lines.len(),
+                lines.len() + skip
+            ),
+        };
+        self.push_system_message(format!("{count} for `{query}`, /jump to one:"));
+        for line in lines {
+            self.push_system_message(line);
+        }
+    }
+
+    /// Switches to the channel of the `n`th search result, scrolled so it's the bottom line
+    fn jump_to_result(&mut self, n: &str) {
+        let result = n
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| self.search_results.get(n.checked_sub(1)?));
+        let Some(result) = result else {
+            return self.push_system_message(format!("no search result {n}, see /search"));
+        };
+
+        let Some(index) = self.tabs.find_index_by_name(&result.channel) else {
+            let msg = format!("{} was left since the search", result.channel);
+            return self.push_system_message(msg);
+        };
+
+        let (timestamp, data) = (result.timestamp, result.data.clone());
+        self.focus_channel(index);
+
+        let output = &self.state.output;
+        let pos = (0..output.len()).rposition(|i| {
+            *output[i].timestamp == timestamp && *output[i].data == data && !*output[i].is_system
+        });
+        let Some(pos) = pos else {
+            return self.push_system_message("that message has fallen out of the scrollback");
+        };
+
+        let below = output.len() - 1 - pos;
+        if let Some(active) = self.tabs.active_mut() {
+            active.scroll = below;
+        }
+        self.update_visible_messages();
+    }
+
+    /// Switches to the tab at `index`, as if the user picked it
+    fn focus_channel(&mut self, index: usize) {
+        let old = self.tabs.active;
+        self.restore_active.take();
+        self.tabs.switch_to_channel(index, &mut self.state.channels);
+        self.tabs.redraw_messages(old, &mut self.state);
+        self.refresh_timestamps(true);
+    }
+
+    /// Switches to the tab for `name`, which can leave off the `#`
+    fn switch_to_named(&mut self, name: &str) {
+        let name = name.trim().to_lowercase();
+        let name = if is_whisper(&name) {
+            name
+        } else {
+            format!("#{}", name.trim_start_matches('#'))
+        };
+
+        match self.tabs.find_index_by_name(&name) {
+            Some(index) => self.focus_channel(index),
+            None => self
+                .push_system_message(format!("you're not in {name}, use /join {name} to join it")),
+        }
+    }
+
+    /// Handles a key press while the channel switcher is open
+    fn switcher_key(&mut self, code: KeyCode) {
+        let Some(switcher) = &mut self.switcher else {
+            return;
+        };
+
+        match code {
+            KeyCode::Char(c) => switcher.query.push(c),
+            KeyCode::Backspace => {
+                switcher.query.pop();
+            }
+            KeyCode::Up | KeyCode::BackTab => switcher.select(-1),
+            KeyCode::Down | KeyCode::Tab => switcher.select(1),
+
+            KeyCode::Enter => {
+                let selected = switcher.selected().map(ToString::to_string);
+                self.switcher.take();
+                if let Some(index) = selected.and_then(|name| self.tabs.find_index_by_name(&name)) {
+                    self.focus_channel(index);
+                }
+            }
+
+            KeyCode::Esc => {
+                self.switcher.take();
+            }
+
+            _ => {}
+        }
+
+        self.update_switcher();
+    }
+
+    /// Re-filters the channels and shows the matches, or hides the switcher once it's closed
+    fn update_switcher(&mut self) {
+        while self.state.switcher_items.pop_front().is_some() {}
+
+        let Some(switcher) = &mut self.switcher else {
+            *self.state.switcher_open = false;
+            return;
+        };
+
+        switcher.update(self.tabs.channels().map(|c| c.name.as_str()));
+        for (i, name) in switcher.matches.iter().enumerate() {
+            let item = SwitcherItem::new(name, i == switcher.selected);
+            self.state.switcher_items.push_back(item);
+        }
+
+        *self.state.switcher_query = switcher.query.clone();
+        *self.state.switcher_open = true;
+    }
+
+    /// Focuses the whisper tab for `user`, opening it if needed
+    fn open_whisper(&mut self, user: &str) {
+        let name = format!("@{user}");
+        self.closed.remove(&name);
+        match self.tabs.find_index_by_name(&name) {
+            Some(index) => self.focus_channel(index),
+            None => {
+                self.restore_active.take();
+                self.tabs.join_channel(&name, &mut self.state);
+                self.update_connected_status();
+            }
+        }
+    }
+
+    /// Shows what we know about `name` from the active channel, without asking twitch
+    fn show_user(&mut self, name: &str) {
+        /// How many of their messages are shown
+        const RECENT: usize = 3;
+
+        let name = name.trim_start_matches('@');
+        let Some(channel) = self.tabs.active() else {
+            return;
+        };
+
+        let roles = channel.chatter_roles(name);
+        let messages = self
+            .tabs
+            .messages(&channel.name, list_items(&self.state.output))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|msg| !*msg.is_system && msg.sender.name.eq_ignore_ascii_case(name))
+            .collect::<Vec<_>>();
+
+        let Some(last) = messages.last() else {
+            let msg = match roles {
+                Some(..) => format!(
+                    "{name} chatted in {}, but their messages are gone",
+                    channel.name
+                ),
+                None => format!("{name} hasn't been seen in {}", channel.name),
+            };
+            return self.push_system_message(msg);
+        };
+
+        let color = match *last.sender.color {
+            anathema::core::Color::Rgb { r, g, b } => format!("#{r:02x}{g:02x}{b:02x}"),
+            color => format!("{color:?}").to_lowercase(),
+        };
+        let roles = roles.map(|roles| roles.names()).unwrap_or_default();
+        let roles = match &*roles {
+            [] => String::from("no roles"),
+            roles => roles.join(", "),
+        };
+
+        let mut lines = vec![format!(
+            "{}: color {color}, {roles}, {} messages in {}",
+            *last.sender.name,
+            messages.len(),
+            channel.name
+        )];
+        lines.extend(
+            messages[messages.len().saturating_sub(RECENT)..]
+                .iter()
+                .map(|msg| format!("  {}{}", *msg.time, *msg.data)),
+        );
+
+        for line in lines {
+            self.push_system_message(line);
+        }
+    }
+
+    /// Writes what the tabs and connection look like to `path`, to attach to bug reports
+    fn dump_state(&mut self, path: &str) {
+        use std::fmt::Write as _;
+
+        let mut out = String::from("# anachat state dump\n");
+        let _ = writeln!(out, "status: {}", self.state.status.as_str());
+        let _ = writeln!(out, "user: {}", *self.state.our_user.name);
+        let _ = writeln!(out, "disconnected: {}", self.disconnected);
+        if let Some(reason) = &self.disconnect_reason {
+            let _ = writeln!(out, "disconnect reason: {reason}");
+        }
+        let _ = writeln!(out, "idle: {}", self.idle);
+        let _ = writeln!(out, "size: {}x{}", self.width, self.height);
+        let _ = writeln!(out, "active: {}", self.tabs.active);
+        let _ = writeln!(out, "tab scroll: {}", self.tabs.scroll);
+        let _ = writeln!(out, "order: {}", self.tabs.order.join(", "));
+        let _ = writeln!(out, "closed: {:?}", self.closed);
+        let _ = writeln!(out, "joining: {:?}", self.joining);
+        let _ = writeln!(out, "joined: {:?}", self.joined);
+        let _ = writeln!(out, "offline queue: {}", self.offline_queue.len());
+        let _ = writeln!(out, "blocked requests: {}", self.blocked_requests.len());
+        let _ = writeln!(out, "output: {}", self.state.output.len());
+
+        for (i, channel) in self.tabs.channels.iter().enumerate() {
+            let messages = self
+                .tabs
+                .messages(&channel.name, list_items(&self.state.output))
+                .map_or(0, |messages| messages.len());
+            let (unread, mentions) = match i < self.state.channels.len() {
+                true => self.state.channels[i].unread_counts(),
+                false => (0, 0),
+            };
+
+            let _ = writeln!(out, "\n[{i}] {}", channel.name);
+            let _ = writeln!(out, "label: {}", channel.label());
+            let _ = writeln!(out, "messages: {messages}");
+            let _ = writeln!(
+                out,
+                "scroll: {}, unseen: {}",
+                channel.scroll, channel.unseen
+            );
+            let _ = writeln!(out, "unread: {unread}, mentions: {mentions}");
+            let _ = writeln!(out, "modes: {}", channel.room_state.modes().join(", "));
+            let _ = writeln!(out, "roles: {:?}", channel.our_roles);
+            if let Some(filter) = &channel.filter {
+                let _ = writeln!(out, "filter: {filter}");
+            }
+        }
+
+        let msg = match std::fs::write(path, redact_oauth(&out)) {
+            Ok(()) => format!("wrote the state to {path}"),
+            Err(err) => format!("cannot write the state to {path}: {err}"),
+        };
+        self.push_system_message(msg);
+    }
+
+    /// Sets (or with `None`, clears) the active channel's filter, `/filters` lists every channel's
+    fn set_filter(&mut self, pattern: Option<&str>) {
+        let filter = match pattern.map(regex::Regex::new).transpose() {
+            Ok(filter) => filter,
+            Err(err) => return self.push_system_message(format!("invalid filter: {err}")),
+        };
+
+        let Some(active) = self.tabs.active_mut() else {
+            return;
+        };
+        let msg = match &filter {
+            Some(filter) => format!("hiding messages matching: {filter}"),
+            None => String::from("filter cleared"),
+        };
+        active.filter = filter;
+        self.push_system_message(msg);
+    }
+
+    /// Shows the filter of every channel that has one
+    fn list_filters(&mut self) {
+        let filters = self
+            .tabs
+            .channels
+            .iter()
+            .filter_map(|channel| Some((&channel.name, channel.filter.as_ref()?)))
+            .map(|(name, filter)| format!("{name}: {filter}"))
+            .collect::<Vec<_>>();
+
+        if filters.is_empty() {
+            return self.push_system_message("no filters are set");
+        }
+        for filter in filters {
+            self.push_system_message(filter);
+        }
+    }
+
+    /// Hides the tab for `channel` while staying joined, a new message re-opens it
+    fn close_channel(&mut self, channel: &str) {
+        self.closed.insert(channel.to_string());
+        self.tabs.part_channel(channel, &mut self.state);
+        self.update_connected_status();
+    }
+
+    /// Re-selects the tab that was active before a reconnect (or quitting) once its channel is re-joined
+    ///
+    /// It's forgotten as soon as the user picks a tab themselves, or once that channel is back and
+    /// the channels joined on startup (which take the focus as their tabs open) are all in
+    fn restore_active_channel(&mut self) {
+        let Some((name, _)) = &self.restore_active else {
+            return;
+        };
+
+        let restored = self.joined.contains(name) && self.startup_joins.is_none();
+        if let Some(index) = self.tabs.find_index_by_name(name) {
+            if index != self.tabs.active {
+                let old = self.tabs.active;
+                self.tabs.switch_to_channel(index, &mut self.state.channels);
+                self.tabs.redraw_messages(old, &mut self.state);
+            }
+        }
+        if restored {
+            self.restore_active.take();
+        }
+    }
+
+    fn check_restore_deadline(&mut self) {
+        let Some((name, Some(deadline))) = &self.restore_active else {
+            return;
+        };
+        if *deadline > Instant::now() {
+            return;
+        }
+
+        let restored = self.joined.contains(name);
+        let msg = format!("could not re-join {name}");
+        self.restore_active.take();
+        if restored {
+            return;
+        }
+
+        // fall back to the first tab, rather than whichever was joined last
+        if !self.tabs.channels.is_empty() && self.tabs.active != 0 {
+            let old = self.tabs.active;
+            self.tabs.switch_to_channel(0, &mut self.state.channels);
+            self.tabs.redraw_messages(old, &mut self.state);
+        }
+        self.push_system_message(msg);
+    }
+
+    /// Says how many of the channels joined on startup were joined, once they all are or we give up
+    fn check_startup_joins(&mut self) {
+        let Some((channels, Some(deadline))) = &self.startup_joins else {
+            return;
+        };
+
+        let missing = channels
+            .iter()
+            .filter(|channel| !self.joined.contains(*channel))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() && *deadline > Instant::now() {
+            return;
+        }
+
+        let total = channels.len();
+        self.startup_joins.take();
+        self.restore_active_channel();
+        let joined = total - missing.len();
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let msg = match missing.len() {
+            0 => format!("joined {total} channel{}", plural(total)),
+            _ => format!(
+                "joined {joined} of {total} channel{}, couldn't join {}",
+                plural(total),
+                missing.join(", ")
+            ),
+        };
+        // it's about startup as a whole, so it goes in the first tab rather than whichever is active
+        let first = self
+            .tabs
+            .channels
+            .first()
+            .map(|channel| channel.name.clone());
+        match first {
+            Some(first) => self.push_system_message_to(&first, msg),
+            None => self.push_system_message(msg),
+        }
+    }
+
+    fn update_status_bar(&mut self) {
+        let (name, modes) = self
+            .tabs
+            .active()
+            .map(|channel| (channel.name.clone(), channel.room_state.modes().join(", ")))
+            .unwrap_or_default();
+
+        let read_only = is_whisper(&name);
+        if *self.state.read_only != read_only {
+            *self.state.read_only = read_only;
+        }
+        if *self.state.active_channel != name {
+            *self.state.active_channel = name;
+        }
+        if *self.state.room_modes != modes {
+            *self.state.room_modes = modes;
+        }
+
+        let beat = self
+            .last_heartbeat
+            .is_some_and(|last| last.elapsed() < Self::HEARTBEAT_BLINK);
+        let heartbeat = if beat { "●" } else { "○" };
+        if *self.state.heartbeat != heartbeat {
+            *self.state.heartbeat = heartbeat.to_string();
+        }
+    }
+
+    fn refresh_timestamps(&mut self, force: bool) {
+        if !force && self.last_time_refresh.elapsed() < Self::TIME_REFRESH {
+            return;
+        }
+        self.last_time_refresh = Instant::now();
+
+        for i in 0..self.state.output.len() {
+            self.state.output[i].format_time(&self.config.timestamp);
+        }
+    }
+
+    /// How many messages can be sent to the active channel per [`twitch::RateLimit::WINDOW`]
+    fn send_capacity(&self) -> usize {
+        let roles = self
+            .tabs
+            .active()
+            .and_then(|channel| channel.our_roles.as_ref());
+        twitch::RateLimit::capacity(roles)
+    }
+
+    /// Shows how many messages can still be sent, or when the next one can be, unless it's all of them
+    fn update_rate_limit(&mut self) {
+        let capacity = self.send_capacity();
+        let limit = &self.rate_limit;
+        let rate_limit = match (limit.remaining(capacity), limit.next_available(capacity)) {
+            (_, Some(wait)) => format!("next send in {}s ", wait.as_millis().div_ceil(1000)),
+            (remaining, None) if remaining == capacity => String::new(),
+            (remaining, None) => format!("{remaining}/{capacity} sends "),
+        };
+
+        if *self.state.rate_limit != rate_limit {
+            *self.state.rate_limit = rate_limit;
+        }
+    }
+
+    fn update_cooldown(&mut self) {
+        let cooldown = self
+            .tabs
+            .active()
+            .and_then(|channel| channel.slow_mode_remaining())
+            .map(|remaining| format!("slow mode: {}s", remaining.as_secs_f32().ceil()))
+            .unwrap_or_default();
+
+        if *self.state.cooldown != cooldown {
+            *self.state.cooldown = cooldown;
+        }
+    }
+
+    /// How many matches `/search` lists, the newest are kept
+    const MAX_SEARCH_RESULTS: usize = 20;
+
+    /// How many of the newest visible lines aren't faded, when older ones are
+    const UNFADED_LINES: usize = 5;
+
+    /// How long a second ctrl-c has to come after the first to quit
+    const QUIT_CONFIRM_WINDOW: Duration = Duration::from_secs(2);
+
+    /// How long to wait for the previously active channel to be re-joined after (re)connecting
+    const RESTORE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How long to wait for the channels joined on startup, after first connecting
+    const STARTUP_JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// How long the heartbeat stays lit
+    const HEARTBEAT_BLINK: Duration = Duration::from_millis(300);
+
+    /// How long a message sent while disconnected is held before it's dropped
+    const OFFLINE_QUEUE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Columns taken by the sidebar, the template reads it from `sidebar_width`
+    const SIDEBAR_WIDTH: usize = 20;
+
+    /// How many messages a mouse wheel step scrolls
+    const SCROLL_LINES: isize = 3;
+
+    /// How often relative timestamps are refreshed
+    const TIME_REFRESH: Duration = Duration::from_secs(10);
+
+    /// Twitch only takes whispers through its API, chat still delivers the ones sent to us
+    const WHISPERS_UNSUPPORTED: &'static str =
+        "twitch no longer takes whispers over chat, they can only be received here";
+
+    /// Twitch ignores this invisible tag character, but it makes the message unique
+    const DUPLICATE_SUFFIX: &'static str = " \u{E0000}";
 }
 
 impl anathema::core::View for RootView {
@@ -36,10 +1569,66 @@ impl anathema::core::View for RootView {
         event: anathema::core::Event,
         _: &mut anathema::core::Nodes<'_>,
     ) -> anathema::core::Event {
+        if matches!(
+            event,
+            anathema::core::Event::KeyPress(..)
+                | anathema::core::Event::Paste(..)
+                | anathema::core::Event::MouseDown(..)
+        ) {
+            self.last_input = Instant::now();
+            if std::mem::take(&mut self.idle) {
+                self.send_request(twitch::Request::Resume);
+            }
+        }
+
+        // without focus events, typing in the active channel is the only sign it has been seen
+        let typed = matches!(event, anathema::core::Event::KeyPress(..));
+        if typed && self.focused.is_none() && self.tabs.active().is_some() {
+            let channel = &mut self.state.channels[self.tabs.active];
+            if channel.unread_counts() != (0, 0) {
+                channel.set_active();
+            }
+        }
+
         match event {
+            anathema::core::Event::KeyPress(anathema::core::KeyCode::Char('c'), modifiers, _)
+                if modifiers == KeyModifiers::CONTROL =>
+            {
+                let confirmed = self
+                    .quit_requested
+                    .is_some_and(|at| at.elapsed() < Self::QUIT_CONFIRM_WINDOW);
+                if confirmed || !self.config.confirm_quit {
+                    return self.quit();
+                }
+
+                self.quit_requested = Some(Instant::now());
+                self.push_system_message("press ctrl-c again to quit");
+            }
+
+            anathema::core::Event::KeyPress(code, modifiers, _) if self.search.is_some() => {
+                self.search_key(code, modifiers);
+            }
+
+            anathema::core::Event::KeyPress(code, _, _) if self.switcher.is_some() => {
+                self.switcher_key(code);
+            }
+
             anathema::core::Event::KeyPress(code, modifiers, _) => match code {
+                anathema::core::KeyCode::Char('r') if modifiers == KeyModifiers::CONTROL => {
+                    self.start_search();
+                }
+
+                anathema::core::KeyCode::Char('k') if modifiers == KeyModifiers::CONTROL => {
+                    self.switcher = Some(Switcher::default());
+                    self.update_switcher();
+                }
+
                 anathema::core::KeyCode::Char(n) if modifiers == KeyModifiers::CONTROL => {
                     let old = self.tabs.active;
+                    if matches!(n, '0'..='9' | 'f' | 'g') {
+                        // the user picked a tab, so don't yank them back after reconnecting
+                        self.restore_active.take();
+                    }
 
                     if n.is_ascii_digit() {
                         let index = (n as u8 - b'0').checked_sub(1).unwrap_or(9) as usize;
@@ -49,79 +1638,271 @@ impl anathema::core::View for RootView {
                     match n {
                         'f' => self.tabs.next_channel(&mut self.state.channels),
                         'g' => self.tabs.previous_channel(&mut self.state.channels),
+                        't' => {
+                            let relative = &mut self.config.timestamp.relative;
+                            *relative = !*relative;
+                        }
+                        'b' => {
+                            let open = &mut *self.state.sidebar_open;
+                            *open = !*open;
+                        }
                         _ => {}
                     }
 
                     self.tabs.redraw_messages(old, &mut self.state);
+                    // switching tabs shows messages that were formatted a while ago
+                    self.refresh_timestamps(true);
                 }
 
                 anathema::core::KeyCode::Char(c) => {
-                    self.state.input.push(c);
+                    self.cursor.insert(&mut self.state.input, c);
+                }
+
+                anathema::core::KeyCode::Left => self.cursor.left(&self.state.input),
+
+                anathema::core::KeyCode::Right => self.cursor.right(&self.state.input),
+
+                anathema::core::KeyCode::Home => self.cursor.home(&self.state.input),
+
+                anathema::core::KeyCode::Tab => self.complete(true),
+
+                anathema::core::KeyCode::BackTab => self.complete(false),
+
+                anathema::core::KeyCode::PageUp => {
+                    self.scroll_by(self.message_rows() as isize);
+                }
+
+                anathema::core::KeyCode::PageDown => {
+                    self.scroll_by(-(self.message_rows() as isize));
+                }
+
+                anathema::core::KeyCode::End => {
+                    let scroll = self.tabs.active().map_or(0, |c| c.scroll);
+                    self.scroll_by(-(scroll as isize));
                 }
 
                 anathema::core::KeyCode::Backspace => {
-                    let _ = self.state.input.pop();
+                    self.cursor.backspace(&mut self.state.input);
                 }
 
                 anathema::core::KeyCode::Enter => {
-                    let data = std::mem::take(&mut *self.state.input);
+                    let mut data = std::mem::take(&mut *self.state.input);
+                    self.history.push(&data);
+
+                    if let Some((command, _)) =
+                        data.split_once('\n').filter(|_| data.starts_with('/'))
+                    {
+                        data = command.to_string();
+                        self.push_system_message(
+                            "only the first line of a command is used, the rest was ignored",
+                        );
+                    }
+
                     match process_input_for_commands(&data) {
                         Command::Join { channel } => {
-                            for channel in channel.split(',') {
-                                let _ = self.send.send_blocking(twitch::Request::JoinChannel {
-                                    channel: channel.to_string(),
-                                });
+                            let mut channels = vec![];
+                            for channel in channel.split(',').map(str::trim) {
+                                if channel.is_empty() {
+                                    continue;
+                                }
+                                let name = format!("#{}", channel.trim_start_matches('#'));
+                                if self.closed.remove(&name) {
+                                    // we never left, so the server won't confirm the join
+                                    self.tabs.join_channel(&name, &mut self.state);
+                                    *self.state.status = ConnectionState::Connected;
+                                    continue;
+                                }
+
+                                self.push_system_message(format!("joining {name}..."));
+                                self.joining.insert(name.to_lowercase());
+                                channels.push(channel.to_string());
+                            }
+
+                            if !channels.is_empty() {
+                                self.send_request(twitch::Request::JoinChannels { channels });
                             }
                         }
                         Command::Part { channel } => {
-                            let _ = self.send.send_blocking(twitch::Request::PartChannel {
-                                channel: channel.to_string(),
-                            });
+                            for channel in channel.split(',').map(str::trim) {
+                                if channel.is_empty() {
+                                    continue;
+                                }
+                                let name = match channel.starts_with('@') {
+                                    true => channel.to_string(),
+                                    false => format!("#{}", channel.trim_start_matches('#')),
+                                };
+
+                                let joined = self.closed.contains(&name)
+                                    || self.tabs.find_index_by_name(&name).is_some();
+                                if !joined {
+                                    self.push_system_message(format!("not in {name}"));
+                                    continue;
+                                }
+                                self.leave_channel(&name);
+                            }
                         }
 
                         Command::PartCurrent => {
                             if let Some(active) = self.tabs.active() {
-                                let _ = self.send.send_blocking(twitch::Request::PartChannel {
-                                    channel: active.name.clone(),
-                                });
+                                self.leave_channel(&active.name.clone());
                             }
                         }
 
-                        Command::Reconnect => {
-                            let _ = self
-                                .send
-                                .send_blocking(twitch::Request::Disconnect { reconnect: true });
+                        Command::Whisper { user } => {
+                            let user = user.trim_start_matches('@').to_lowercase();
+                            self.open_whisper(&user);
+                        }
+
+                        Command::Close { channel } => {
+                            self.close_channel(channel);
+                        }
+
+                        Command::CloseCurrent => {
+                            if let Some(active) = self.tabs.active() {
+                                self.close_channel(&active.name.clone());
+                            }
+                        }
+
+                        Command::Switch { channel } => self.switch_to_named(channel),
+
+                        Command::Alias { alias } => self.set_alias(alias),
+
+                        Command::Filter { pattern } => self.set_filter(pattern),
+
+                        Command::Filters => self.list_filters(),
+
+                        Command::Search { query } => self.search_all(query),
+
+                        Command::Jump { result } => self.jump_to_result(result),
+
+                        Command::Notify { notify } => self.set_notify(notify),
+
+                        Command::Dump { path } => {
+                            self.dump_state(path.unwrap_or("anachat-dump.txt"))
+                        }
+
+                        Command::User { name } => self.show_user(name),
+
+                        Command::Help => {
+                            for (usage, description) in COMMANDS {
+                                self.push_system_message(format!("{usage:<24} {description}"));
+                            }
                         }
 
-                        Command::Quit => {
-                            let _ = self
-                                .send
-                                .send_blocking(twitch::Request::Disconnect { reconnect: false });
+                        Command::Timestamps { show } => {
+                            let show = show.unwrap_or(self.config.timestamp.hidden);
+                            self.config.timestamp.hidden = !show;
+                            self.refresh_timestamps(true);
+                            self.push_system_message(match show {
+                                true => "timestamps are shown",
+                                false => "timestamps are hidden",
+                            });
+                            self.session.timestamps = Some(show);
+                            self.save_session();
+                        }
 
-                            return anathema::core::Event::Stop;
+                        // this also holds back messages until we're reconnected
+                        Command::Reconnect
+                            if self.disconnected
+                                && *self.state.status != ConnectionState::ReplayEnded =>
+                        {
+                            self.push_system_message("already reconnecting");
                         }
 
-                        Command::Error { msg: _ } => {
-                            // we need a synthetic buffer to show these errors
+                        Command::Reconnect => {
+                            self.disconnected = true;
+                            self.push_system_message("reconnecting...");
+                            self.send_request(twitch::Request::Disconnect { reconnect: true });
                         }
 
-                        Command::None => {
-                            if let Some(active) = self.tabs.active() {
-                                let _ = self.send.send_blocking(twitch::Request::SendMesage {
-                                    channel: active.name.clone(),
-                                    data,
-                                });
+                        Command::Resync => match self.tabs.active_mut() {
+                            Some(channel) if channel.is_channel() => {
+                                channel.reset_chatters();
+                                let msg = format!(
+                                    "forgot the chatters of {}, they're tracked again as they chat",
+                                    channel.name
+                                );
+                                self.push_system_message(msg);
+                            }
+                            _ => self.push_system_message("this tab isn't a channel"),
+                        },
+
+                        Command::Quit => return self.quit(),
+
+                        Command::Error { msg } => {
+                            self.push_system_message(msg);
+                        }
+
+                        Command::Twitch => {
+                            // twitch handles these itself
+                            match self.tabs.active() {
+                                Some(active) if active.is_channel() => {
+                                    let channel = active.name.clone();
+                                    if self.check_sendable(&data) {
+                                        self.send_chat(twitch::Request::SendMesage {
+                                            channel,
+                                            data,
+                                        });
+                                    }
+                                }
+                                Some(..) => {
+                                    self.push_system_message("that only works in a channel");
+                                }
+                                None => {}
                             }
                         }
+
+                        Command::None => self.send_lines(data),
                     }
                 }
                 _ => {}
             },
 
-            anathema::core::Event::MouseDown(x, y, _, _) => {
+            anathema::core::Event::Paste(ref text) => {
+                self.state.input.push_str(&normalize_newlines(text));
+            }
+
+            anathema::core::Event::Focus => {
+                self.focused = Some(true);
+                if self.tabs.active().is_some() {
+                    // the user can see the active channel again, but may not have noticed a mention
+                    self.state.channels[self.tabs.active].mark_read();
+                }
+            }
+
+            anathema::core::Event::Blur => {
+                self.focused = Some(false);
+            }
+
+            anathema::core::Event::MouseScrollUp(..) => {
+                self.scroll_by(Self::SCROLL_LINES);
+            }
+
+            anathema::core::Event::MouseScrollDown(..) => {
+                self.scroll_by(-Self::SCROLL_LINES);
+            }
+
+            anathema::core::Event::Resize(width, height) => {
+                self.width = width;
+                self.height = height;
+                self.update_visible_messages();
+                // every tab moves (or disappears) when the width changes
+                self.regions.clear();
+                self.update_tab_scroll();
+            }
+
+            anathema::core::Event::MouseDown(x, y, _, button) => {
                 let old = self.tabs.active;
-                if let Some(name) = tab::TabRegions::containing_point(pos2(x, y)) {
-                    if let Some(index) = self.tabs.find_index_by_name(&*name) {
+                let index = self.sidebar_channel_at(x, y).or_else(|| {
+                    let name = self.regions.containing_point(pos2(x, y))?;
+                    self.tabs.find_index_by_label(&name)
+                });
+
+                if let Some(index) = index {
+                    if button == MouseButton::Middle {
+                        self.leave_channel(&self.tabs.channels[index].name.clone());
+                    } else {
+                        self.restore_active.take();
                         self.tabs.switch_to_channel(index, &mut self.state.channels);
                         self.tabs.redraw_messages(old, &mut self.state);
                     }
@@ -137,68 +1918,321 @@ impl anathema::core::View for RootView {
         while let Ok(msg) = self.feed.try_recv() {
             match msg {
                 twitch::Response::Message { message } => {
-                    let channel_pos = self
+                    if self.tabs.find_index_by_name(&message.channel).is_none() {
+                        // we're still joined to channels whose tabs were closed
+                        self.closed.remove(&message.channel);
+                        self.tabs.reopen_channel(&message.channel, &mut self.state);
+                        self.apply_channel_settings(&message.channel);
+                        *self.state.status = ConnectionState::Connected;
+                    }
+
+                    let channel_pos = self.tabs.find_index_by_name(&message.channel);
+                    if self
                         .tabs
-                        .channels
-                        .iter()
-                        .position(|c| c.name == message.channel);
+                        .channel(&message.channel)
+                        .is_some_and(|channel| channel.is_filtered(&message.data))
+                    {
+                        continue;
+                    }
+
+                    if let Some(index) = channel_pos {
+                        self.tabs.channels[index]
+                            .add_recent_chatter(&message.sender.name, message.roles);
+                    }
+
+                    let own = message.sender.name == *self.state.our_user.name;
+                    let roles = message.roles;
+                    let first = message.tag("first-msg") == Some("1");
+                    // messages from the other channels in a shared chat
+                    let source = message
+                        .tag("source-room-id")
+                        .filter(|&source| Some(source) != message.tag("room-id"))
+                        .map(|source| {
+                            self.tabs
+                                .channels()
+                                .find(|c| c.room_state.room_id.as_deref() == Some(source))
+                                .map_or_else(|| String::from("shared chat"), |c| c.name.clone())
+                        });
+                    let mention =
+                        !own && (self.is_mention(&message.data) || is_whisper(&message.channel));
+                    let notify = channel_pos
+                        .and_then(|index| self.tabs.channels[index].notify)
+                        .unwrap_or(self.config.notify);
+                    let ring = match notify {
+                        Notify::All => !own,
+                        Notify::Mentions => mention,
+                        Notify::None => false,
+                    };
+                    if ring {
+                        self.ring_bell();
+                    }
+
+                    let mut message = model::AnaMessage::from(message);
+                    message.format_time(&self.config.timestamp);
+                    if let Some(color) =
+                        model::role_color(roles).filter(|_| self.config.role_colors)
+                    {
+                        *message.sender.color = color;
+                    }
+                    if own && self.config.highlight_own {
+                        *message.marker = String::from("› ");
+                    }
+                    if first && self.config.highlight_first {
+                        *message.marker = String::from("✨ first time ");
+                    }
+                    if let Some(source) = source {
+                        *message.marker = format!("[{source}] ");
+                    }
 
-                    if let Some(index) = channel_pos
-                        .filter(|_| self.tabs.active().map(|c| &c.name) != Some(&message.channel))
+                    if self.collapse_repeat(channel_pos, &message) {
+                        continue;
+                    }
+
+                    if let Some(index) =
+                        channel_pos.filter(|_| self.tabs.active_name() != Some(&*message.channel))
                     {
-                        self.tabs.channels[index].push_message(message);
-                        if let Some(pos) = channel_pos {
-                            self.state.channels[pos].set_unread_messages();
-                            self.tabs.channels[pos].set_unread_messages();
+                        self.tabs.channels[index].push_message(message, self.config.scrollback);
+                        self.state.channels[index].add_unread(mention);
+                        if mention {
+                            self.tabs.channels[index].set_unread_mentions();
+                        } else {
+                            self.tabs.channels[index].set_unread_messages();
                         }
                     } else {
-                        self.state.output.push_back(message.into())
+                        // the active channel isn't being read while the terminal is unfocused
+                        if let Some(index) = channel_pos.filter(|_| self.focused == Some(false)) {
+                            self.state.channels[index].add_unread(mention);
+                        }
+                        self.push_output(message)
+                    }
+                }
+
+                twitch::Response::RoomState { channel, state } => {
+                    if let Some(index) = self.tabs.find_index_by_name(&channel) {
+                        let room_state = &mut self.tabs.channels[index].room_state;
+                        room_state.merge(state);
+                        let modes = room_state.glyphs(self.config.ascii_glyphs);
+                        if *self.state.channels[index].modes != modes {
+                            *self.state.channels[index].modes = modes;
+                            // the tab changed width, so every tab after it moved
+                            self.regions.clear();
+                        }
+                    }
+                }
+
+                twitch::Response::ClearMessage { channel, id } => {
+                    self.clear_message(&channel, &id);
+                }
+
+                twitch::Response::UserState { channel, roles } => {
+                    if let Some(index) = self.tabs.find_index_by_name(&channel) {
+                        self.tabs.channels[index].our_roles.replace(roles);
                     }
                 }
 
+                twitch::Response::Notice { channel, message } => match channel {
+                    // there's no tab for it yet, so say why where the join was asked for
+                    Some(channel) if self.joining.remove(&channel) => {
+                        self.push_system_message(format!("couldn't join {channel}: {message}"));
+                    }
+                    Some(channel) => self.push_system_message_to(&channel, message),
+                    None => self.push_system_message(message),
+                },
+
+                twitch::Response::Host {
+                    channel,
+                    target,
+                    viewers,
+                } => {
+                    let msg = match (target, viewers) {
+                        (Some(target), Some(viewers)) => {
+                            format!("now hosting {target} for {viewers} viewers")
+                        }
+                        (Some(target), None) => format!("now hosting {target}"),
+                        (None, _) => String::from("exited host mode"),
+                    };
+                    self.push_system_message_to(&channel, msg);
+                }
+
                 twitch::Response::Connecting => {
-                    *self.state.status = String::from(Self::CONNECTING);
+                    *self.state.status = ConnectionState::Connecting;
                 }
 
                 twitch::Response::Connected { user } => {
-                    self.state.our_user = StateValue::new(user.into());
-                    let status = if self.state.channels.is_empty() {
-                        Self::ON_NO_CHANNELS
-                    } else {
-                        Self::CONNECTED
-                    };
-                    *self.state.status = String::from(status);
+                    if std::mem::take(&mut self.disconnected) {
+                        self.push_system_message_to_all("— reconnected —");
+                        // who was around before the disconnect isn't known to still be there
+                        let mut reset = vec![];
+                        for channel in &mut self.tabs.channels {
+                            channel.reset_chatters();
+                            if channel.is_channel() {
+                                reset.push(channel.name.clone());
+                            }
+                        }
+                        for channel in reset {
+                            self.push_system_message_to(
+                                &channel,
+                                "forgot the recent chatters, they're tracked again as they chat",
+                            );
+                        }
+                    }
+                    self.reconnect_at = None;
+                    self.disconnect_reason = None;
+                    self.update_reconnect_status();
+                    if let Some(user) = user {
+                        self.state.our_user = StateValue::new(user.into());
+                    }
+                    if let Some((_, deadline)) = &mut self.restore_active {
+                        deadline.replace(Instant::now() + Self::RESTORE_TIMEOUT);
+                    }
+                    if let Some((_, deadline @ None)) = &mut self.startup_joins {
+                        deadline.replace(Instant::now() + Self::STARTUP_JOIN_TIMEOUT);
+                    }
+                    self.update_connected_status();
+                }
+
+                twitch::Response::Disconnected {
+                    reason: twitch::DisconnectReason::Idle,
+                } => {
+                    self.joined.clear();
+                    if !std::mem::replace(&mut self.disconnected, true) {
+                        self.push_system_message_to_all(
+                            "— disconnected while idle, press a key to reconnect —",
+                        );
+                    }
+                    if self.restore_active.is_none() {
+                        self.restore_active = self
+                            .tabs
+                            .active()
+                            .map(|channel| (channel.name.clone(), None));
+                    }
+                    *self.state.status = ConnectionState::Idle;
+                }
+
+                twitch::Response::Disconnected {
+                    reason: twitch::DisconnectReason::ReplayEnded,
+                } => {
+                    self.joined.clear();
+                    self.push_system_message_to_all(
+                        "— end of replay, /reconnect to play it again —",
+                    );
+                    *self.state.status = ConnectionState::ReplayEnded;
+                }
+
+                // we're shutting down, there's nothing to show
+                twitch::Response::Disconnected {
+                    reason: twitch::DisconnectReason::Quit,
+                } => {}
+
+                twitch::Response::Disconnected { reason } => {
+                    // we'll re-join them once we've reconnected
+                    self.joined.clear();
+                    // this is sent for every failed attempt, but the gap only needs marking once
+                    if !std::mem::replace(&mut self.disconnected, true) {
+                        self.push_system_message_to_all(format!("— disconnected: {reason} —"));
+                    }
+                    self.disconnect_reason = Some(reason);
+                    if self.restore_active.is_none() {
+                        self.restore_active = self
+                            .tabs
+                            .active()
+                            .map(|channel| (channel.name.clone(), None));
+                    }
+                    *self.state.status = ConnectionState::Reconnecting;
+                }
+
+                twitch::Response::ServerReconnect => {
+                    // this also holds back messages, and the disconnect that follows isn't marked again
+                    if !std::mem::replace(&mut self.disconnected, true) {
+                        self.push_system_message_to_all(
+                            "— the server asked us to reconnect, channels will be re-joined —",
+                        );
+                    }
+                }
+
+                twitch::Response::Heartbeat => {
+                    self.last_heartbeat.replace(Instant::now());
+                }
+
+                twitch::Response::RateLimit { limit } => {
+                    self.rate_limit = limit;
+                }
+
+                twitch::Response::Reconnecting { attempt, delay } => {
+                    self.reconnect_at = Some((attempt, Instant::now() + delay));
+                    self.update_reconnect_status();
                 }
 
-                twitch::Response::Disconnected => {
-                    *self.state.status = String::from(Self::RECONNECTING);
+                twitch::Response::GaveUp { .. } => {
+                    *self.state.status = ConnectionState::GaveUp;
                 }
 
                 twitch::Response::AuthenticationFailed => {
-                    *self.state.status = String::from(Self::INVALID_AUTH);
+                    *self.state.status = ConnectionState::InvalidAuth;
                 }
 
                 twitch::Response::JoinChannel { channel } => {
-                    self.tabs.join_channel(&channel, &mut self.state);
-                    let status = if self.state.channels.is_empty() {
-                        Self::ON_NO_CHANNELS
-                    } else {
-                        Self::CONNECTED
-                    };
-                    *self.state.status = String::from(status);
+                    self.joined.insert(channel.clone());
+                    if self.joining.remove(&channel) {
+                        self.push_system_message(format!("joined {channel}"));
+                        // the user asked for this one, so it keeps the focus
+                        self.restore_active.take();
+                    }
+                    if !self.closed.contains(&channel) {
+                        self.tabs.join_channel(&channel, &mut self.state);
+                        self.apply_channel_settings(&channel);
+                        if let Some((messages, mentions)) = self.session.unread.remove(&channel) {
+                            if let Some(index) = self.tabs.find_index_by_name(&channel) {
+                                self.state.channels[index].restore_unread(messages, mentions);
+                            }
+                        }
+                        self.restore_active_channel();
+                        self.save_session();
+                    }
+                    self.check_startup_joins();
+                    self.update_connected_status();
                 }
 
                 twitch::Response::PartChannel { channel } => {
+                    self.joined.remove(&channel);
+                    self.closed.remove(&channel);
                     self.tabs.part_channel(&channel, &mut self.state);
-                    let status = if self.state.channels.is_empty() {
-                        Self::ON_NO_CHANNELS
-                    } else {
-                        Self::CONNECTED
-                    };
-                    *self.state.status = String::from(status);
+                    self.tabs.order.retain(|c| *c != channel);
+                    self.save_session();
+                    self.update_connected_status();
+                }
+
+                twitch::Response::Membership { channel, joined } => {
+                    if self.config.membership_summary.is_some() {
+                        if let Some(index) = self.tabs.find_index_by_name(&channel) {
+                            self.tabs.channels[index].add_membership(joined);
+                        }
+                    }
                 }
             }
         }
+
+        self.check_idle();
+        self.summarize_membership();
+        self.check_restore_deadline();
+        self.check_startup_joins();
+        self.flush_blocked_requests();
+        self.drain_offline_queue();
+        self.update_reconnect_status();
+        self.update_completion();
+        self.update_input_view();
+        self.refresh_timestamps(false);
+        self.update_cooldown();
+        self.update_rate_limit();
+        self.update_status_bar();
+        self.update_tab_scroll();
+        self.update_visible_messages();
+
+        // the unread counts change too often to save every time, so they're kept for exiting
+        if self.config.session.is_some() {
+            self.update_session();
+            *self.exit_session.lock().unwrap() = self.session.clone();
+        }
     }
 
     fn state(&self) -> &dyn anathema::values::State {
@@ -206,20 +2240,103 @@ impl anathema::core::View for RootView {
     }
 }
 
+/// Whether `channel` is a `@user` whisper tab rather than a twitch channel
+fn is_whisper(channel: &str) -> bool {
+    channel.starts_with('@')
+}
+
+/// The items in `list`, front to back
+fn list_items<T>(list: &List<T>) -> impl Iterator<Item = &T> {
+    (0..list.len()).map(|i| &list[i])
+}
+
+/// Turns `\r\n` and lone `\r` into `\n`
+fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
 fn process_input_for_commands<'a>(input: &'a str) -> Command<'a> {
-    if let Some((key, val)) = input.strip_prefix('/').and_then(|s| {
-        s.split_once(' ')
-            .map(|(a, b)| (a, Some(b)))
-            .or_else(|| Some((s, None)))
+    if let Some((key, val)) = input.strip_prefix('/').map(|s| {
+        let (key, val) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        (key, Some(val.trim()).filter(|val| !val.is_empty()))
     }) {
         match (key, val) {
-            ("join", Some(val)) => Command::Join { channel: val },
+            ("", _) => Command::Error {
+                msg: String::from("missing a command, see /help"),
+            },
+            ("join", Some(val)) => {
+                let invalid = val
+                    .split(',')
+                    .map(str::trim)
+                    .find(|channel| !channel.is_empty() && !is_valid_name(channel));
+                match invalid {
+                    Some(channel) => Command::Error {
+                        msg: format!(
+                            "{channel:?} isn't a channel name, those are letters, digits and underscores"
+                        ),
+                    },
+                    None => Command::Join { channel: val },
+                }
+            }
+            ("join", None) => Command::Error {
+                msg: String::from("usage: /join #channel[,...]"),
+            },
             ("part", Some(val)) => Command::Part { channel: val },
             ("part", None) => Command::PartCurrent,
+            ("close", Some(val)) => Command::Close { channel: val },
+            ("close", None) => Command::CloseCurrent,
+            ("w", Some(val)) if val.contains(' ') => Command::Error {
+                msg: String::from(RootView::WHISPERS_UNSUPPORTED),
+            },
+            ("w", Some(val)) => Command::Whisper { user: val },
+            ("w", None) => Command::Error {
+                msg: String::from("usage: /w user"),
+            },
+            ("alias", val) => Command::Alias { alias: val },
+            ("switch" | "go", Some(val)) => Command::Switch { channel: val },
+            ("switch" | "go", None) => Command::Error {
+                msg: format!("usage: /{key} #channel"),
+            },
+            ("filter", val) => Command::Filter { pattern: val },
+            ("filters", _) => Command::Filters,
+            ("search", Some(val)) => Command::Search { query: val },
+            ("search", None) => Command::Error {
+                msg: String::from("usage: /search text"),
+            },
+            ("jump", Some(val)) => Command::Jump { result: val },
+            ("jump", None) => Command::Error {
+                msg: String::from("usage: /jump n, where n is from /search"),
+            },
+            ("notify", None) => Command::Notify { notify: None },
+            ("notify", Some(val)) => match Notify::parse(val) {
+                Some(notify) => Command::Notify {
+                    notify: Some(notify),
+                },
+                None => Command::Error {
+                    msg: String::from("usage: /notify [all|mentions|none]"),
+                },
+            },
+            ("dump", path) => Command::Dump { path },
+            ("user", Some(val)) => Command::User { name: val },
+            ("user", None) => Command::Error {
+                msg: String::from("usage: /user name"),
+            },
+            ("help", _) => Command::Help,
+            ("timestamps", None) => Command::Timestamps { show: None },
+            ("timestamps", Some("on")) => Command::Timestamps { show: Some(true) },
+            ("timestamps", Some("off")) => Command::Timestamps { show: Some(false) },
+            ("timestamps", Some(..)) => Command::Error {
+                msg: String::from("usage: /timestamps [on|off]"),
+            },
             ("reconnect", _) => Command::Reconnect,
+            ("resync", _) => Command::Resync,
             ("quit", _) => Command::Quit,
+            (key, _) if TWITCH_COMMANDS.contains(&key) => Command::Twitch,
+            (key, _) if UNSUPPORTED_COMMANDS.contains(&key) => Command::Error {
+                msg: format!("twitch no longer takes /{key} over chat, use its website instead"),
+            },
             _ => Command::Error {
-                msg: format!("unknown command: '{key}' (args: [{val:?}]"),
+                msg: format!("unknown command: /{key}, see /help"),
             },
         }
     } else {
@@ -227,12 +2344,614 @@ fn process_input_for_commands<'a>(input: &'a str) -> Command<'a> {
     }
 }
 
+/// A message found by `/search`, matched up again by its time and text when it's jumped to
+#[derive(Debug)]
+struct SearchResult {
+    channel: String,
+    timestamp: i64,
+    sender: String,
+    data: String,
+    /// The formatted time, with a space after it
+    time: String,
+}
+
+/// Replaces anything that looks like an oauth token, in case one ever ends up in a dump
+fn redact_oauth(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find("oauth:") {
+        let (head, tail) = rest.split_at(pos + "oauth:".len());
+        out.push_str(head);
+        out.push_str("<redacted>");
+        let end = tail
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(tail.len());
+        rest = &tail[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Chat commands that are sent to the active channel as-is, for twitch to handle
+const TWITCH_COMMANDS: &[&str] = &["me"];
+
+/// Chat commands twitch only takes through its API now, sent as chat they'd do nothing
+const UNSUPPORTED_COMMANDS: &[&str] = &[
+    "raid",
+    "unraid",
+    "ban",
+    "unban",
+    "timeout",
+    "untimeout",
+    "delete",
+    "clear",
+    "slow",
+    "slowoff",
+    "emoteonly",
+    "emoteonlyoff",
+    "followers",
+    "followersoff",
+    "subscribers",
+    "subscribersoff",
+    "uniquechat",
+    "uniquechatoff",
+];
+
+/// The name of every command, without its `/`
+fn command_names() -> impl Iterator<Item = &'static str> {
+    COMMANDS
+        .iter()
+        .filter_map(|(usage, _)| usage.split(' ').next()?.strip_prefix('/'))
+        .chain(TWITCH_COMMANDS.iter().copied())
+}
+
+/// Usage and a description of each command, for /help
+const COMMANDS: &[(&str, &str)] = &[
+    ("/join #channel[,...]", "join one or more channels"),
+    (
+        "/part [#channel[,...]]",
+        "leave one or more channels, or the current one",
+    ),
+    (
+        "/close [#channel]",
+        "hide a tab but stay joined, new messages re-open it",
+    ),
+    ("/me action", "describe an action, like /me waves"),
+    ("/w user", "open a read-only whisper tab for a user"),
+    (
+        "/switch #channel",
+        "switch to a channel's tab, /go also works",
+    ),
+    (
+        "/alias [name]",
+        "show a name on this tab instead, without one clears it",
+    ),
+    (
+        "/filter [regex]",
+        "hide messages in this channel matching a regex, without one clears it",
+    ),
+    ("/filters", "show every channel's filter"),
+    (
+        "/search text",
+        "find messages in every channel, ignoring case",
+    ),
+    ("/jump n", "show the nth /search match in its channel"),
+    (
+        "/notify [all|mentions|none]",
+        "which messages ring the bell in this channel",
+    ),
+    (
+        "/user name",
+        "show what we've seen of a user in this channel",
+    ),
+    (
+        "/timestamps [on|off]",
+        "show or hide timestamps, without an argument toggles them",
+    ),
+    ("/reconnect", "force a reconnect"),
+    (
+        "/dump [file]",
+        "write the state of the tabs to a file, for bug reports",
+    ),
+    (
+        "/resync",
+        "forget this channel's recent chatters, e.g. for completion",
+    ),
+    ("/quit", "disconnect and exit"),
+    ("/help", "show this help"),
+];
+
 enum Command<'a> {
-    Join { channel: &'a str },
-    Part { channel: &'a str },
+    Join {
+        channel: &'a str,
+    },
+    Part {
+        channel: &'a str,
+    },
     PartCurrent,
+    Close {
+        channel: &'a str,
+    },
+    CloseCurrent,
+    Whisper {
+        user: &'a str,
+    },
+    Switch {
+        channel: &'a str,
+    },
+    Alias {
+        alias: Option<&'a str>,
+    },
+    Filter {
+        pattern: Option<&'a str>,
+    },
+    Filters,
+    Search {
+        query: &'a str,
+    },
+    Jump {
+        result: &'a str,
+    },
+    Notify {
+        notify: Option<Notify>,
+    },
+    Dump {
+        path: Option<&'a str>,
+    },
+    User {
+        name: &'a str,
+    },
+    Help,
+    Timestamps {
+        show: Option<bool>,
+    },
+    /// Reconnects the one connection, whichever tab is active
     Reconnect,
+    Resync,
     Quit,
+    /// One of [`TWITCH_COMMANDS`]
+    Twitch,
     None,
-    Error { msg: String },
+    Error {
+        msg: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use anathema::core::View;
+
+    use super::*;
+    use crate::{
+        config::Palette,
+        timestamp::{TimestampFormat, Zone},
+    };
+
+    fn parse(input: &str) -> Command<'_> {
+        process_input_for_commands(input)
+    }
+
+    fn is_error(command: Command<'_>) -> bool {
+        matches!(command, Command::Error { .. })
+    }
+
+    /// A view whose connection takes up to `capacity` requests before it's read from
+    fn view(capacity: usize) -> (RootView, Receiver<twitch::Request>) {
+        let (view, _, requests) = launched(Session::default(), &[], capacity);
+        (view, requests)
+    }
+
+    /// The default settings, without reading the environment
+    ///
+    /// Nothing is saved, and the bell never rings so tests don't write to the terminal
+    fn config() -> Config {
+        Config {
+            timestamp: TimestampFormat::new(TimestampFormat::DEFAULT_FORMAT, Zone::Utc, false),
+            template: None,
+            slow_mode_block: true,
+            highlight_own: false,
+            highlight_first: true,
+            role_colors: false,
+            group_messages: false,
+            collapse_repeats: false,
+            duplicate_messages: DuplicateMessages::Bypass,
+            long_messages: LongMessages::Wrap,
+            session: None,
+            scrollback: 1000,
+            idle_disconnect: None,
+            membership_summary: Some(Duration::from_secs(60)),
+            palette: Palette::Default,
+            fade_older: false,
+            notify: Notify::None,
+            confirm_quit: true,
+            tab_numbers: false,
+            ascii_glyphs: false,
+            cursor: String::from("█"),
+            cursor_color: anathema::core::Color::Rgb {
+                r: 0,
+                g: 170,
+                b: 170,
+            },
+        }
+    }
+
+    /// A view started with `session` and `auto_join`, along with its ends of the connection
+    fn launched(
+        session: Session,
+        auto_join: &[String],
+        capacity: usize,
+    ) -> (
+        RootView,
+        Sender<twitch::Response>,
+        Receiver<twitch::Request>,
+    ) {
+        let (responses, feed) = smol::channel::unbounded();
+        let (send, requests) = smol::channel::bounded(capacity);
+        let view = RootView::new(
+            config(),
+            session,
+            Default::default(),
+            auto_join,
+            tab::TabRegions::default(),
+            tab::TabRegions::default(),
+            feed,
+            send,
+        );
+        (view, responses, requests)
+    }
+
+    /// Starts a view that was last left on `active`, and connects it
+    fn restoring(
+        active: &str,
+        auto_join: &[&str],
+    ) -> (
+        RootView,
+        Sender<twitch::Response>,
+        Receiver<twitch::Request>,
+    ) {
+        let session = Session {
+            active: Some(active.into()),
+            ..Session::default()
+        };
+        let auto_join = auto_join
+            .iter()
+            .map(|&c| String::from(c))
+            .collect::<Vec<_>>();
+        let (view, responses, requests) = launched(session, &auto_join, 64);
+        responses
+            .try_send(twitch::Response::Connected { user: None })
+            .unwrap();
+        (view, responses, requests)
+    }
+
+    fn join(responses: &Sender<twitch::Response>, channel: &str) {
+        responses
+            .try_send(twitch::Response::JoinChannel {
+                channel: channel.into(),
+            })
+            .unwrap();
+    }
+
+    fn parted(request: twitch::Request) -> String {
+        match request {
+            twitch::Request::PartChannel { channel } => channel,
+            _ => panic!("expected a part"),
+        }
+    }
+
+    #[test]
+    fn channel_commands() {
+        assert!(matches!(
+            parse("/join #foo"),
+            Command::Join { channel: "#foo" }
+        ));
+        assert!(matches!(
+            parse("/join foo, #bar,baz"),
+            Command::Join {
+                channel: "foo, #bar,baz"
+            }
+        ));
+        assert!(is_error(parse("/join #foo,b@r")));
+        assert!(is_error(parse("/join")));
+
+        assert!(matches!(
+            parse("/part #foo"),
+            Command::Part { channel: "#foo" }
+        ));
+        assert!(matches!(parse("/part"), Command::PartCurrent));
+        assert!(matches!(
+            parse("/close #foo"),
+            Command::Close { channel: "#foo" }
+        ));
+        assert!(matches!(parse("/close"), Command::CloseCurrent));
+
+        assert!(matches!(
+            parse("/switch #foo"),
+            Command::Switch { channel: "#foo" }
+        ));
+        assert!(matches!(
+            parse("/go foo"),
+            Command::Switch { channel: "foo" }
+        ));
+        assert!(is_error(parse("/switch")));
+        assert!(is_error(parse("/go")));
+    }
+
+    #[test]
+    fn whisper_commands() {
+        assert!(is_error(parse("/w someone hello there")));
+        assert!(matches!(
+            parse("/w someone"),
+            Command::Whisper { user: "someone" }
+        ));
+        assert!(is_error(parse("/w")));
+    }
+
+    #[test]
+    fn tab_settings() {
+        assert!(matches!(
+            parse("/alias foo"),
+            Command::Alias { alias: Some("foo") }
+        ));
+        assert!(matches!(parse("/alias"), Command::Alias { alias: None }));
+
+        assert!(matches!(
+            parse("/filter ^!\\w+"),
+            Command::Filter {
+                pattern: Some("^!\\w+")
+            }
+        ));
+        assert!(matches!(
+            parse("/filter"),
+            Command::Filter { pattern: None }
+        ));
+        assert!(matches!(
+            parse("/filter list"),
+            Command::Filter {
+                pattern: Some("list")
+            }
+        ));
+        assert!(matches!(parse("/filters"), Command::Filters));
+
+        assert!(matches!(
+            parse("/notify all"),
+            Command::Notify {
+                notify: Some(Notify::All)
+            }
+        ));
+        assert!(matches!(
+            parse("/notify mentions"),
+            Command::Notify {
+                notify: Some(Notify::Mentions)
+            }
+        ));
+        assert!(matches!(
+            parse("/notify none"),
+            Command::Notify {
+                notify: Some(Notify::None)
+            }
+        ));
+        assert!(matches!(parse("/notify"), Command::Notify { notify: None }));
+        assert!(is_error(parse("/notify sometimes")));
+
+        assert!(matches!(
+            parse("/timestamps on"),
+            Command::Timestamps { show: Some(true) }
+        ));
+        assert!(matches!(
+            parse("/timestamps off"),
+            Command::Timestamps { show: Some(false) }
+        ));
+        assert!(matches!(
+            parse("/timestamps"),
+            Command::Timestamps { show: None }
+        ));
+        assert!(is_error(parse("/timestamps maybe")));
+    }
+
+    #[test]
+    fn lookup_commands() {
+        assert!(matches!(
+            parse("/search some text"),
+            Command::Search { query: "some text" }
+        ));
+        assert!(is_error(parse("/search")));
+        assert!(matches!(parse("/jump 3"), Command::Jump { result: "3" }));
+        assert!(is_error(parse("/jump")));
+        assert!(matches!(
+            parse("/user someone"),
+            Command::User { name: "someone" }
+        ));
+        assert!(is_error(parse("/user")));
+        assert!(matches!(
+            parse("/dump out.txt"),
+            Command::Dump {
+                path: Some("out.txt")
+            }
+        ));
+        assert!(matches!(parse("/dump"), Command::Dump { path: None }));
+    }
+
+    #[test]
+    fn commands_without_arguments() {
+        assert!(matches!(parse("/help"), Command::Help));
+        assert!(matches!(parse("/reconnect"), Command::Reconnect));
+        assert!(matches!(parse("/resync"), Command::Resync));
+        assert!(matches!(parse("/quit"), Command::Quit));
+        // surrounding whitespace is ignored
+        assert!(matches!(parse("/quit  "), Command::Quit));
+    }
+
+    #[test]
+    fn unknown_and_missing_commands() {
+        assert!(is_error(parse("/nope")));
+        assert!(is_error(parse("/nope with args")));
+        assert!(is_error(parse("/")));
+        assert!(is_error(parse("/   ")));
+    }
+
+    #[test]
+    fn twitch_commands_are_passed_through() {
+        for command in TWITCH_COMMANDS {
+            let input = format!("/{command} someone");
+            assert!(matches!(parse(&input), Command::Twitch), "{input}");
+        }
+        assert!(matches!(parse("/me"), Command::Twitch));
+    }
+
+    #[test]
+    fn unsupported_twitch_commands_arent_sent() {
+        for command in UNSUPPORTED_COMMANDS {
+            let input = format!("/{command} someone");
+            assert!(is_error(parse(&input)), "{input}");
+        }
+        assert!(is_error(parse("/ban")));
+    }
+
+    #[test]
+    fn plain_messages_arent_commands() {
+        assert!(matches!(parse("hello"), Command::None));
+        assert!(matches!(parse(" /quit"), Command::None));
+        assert!(matches!(parse(""), Command::None));
+    }
+
+    #[test]
+    fn requests_wait_for_a_stalled_connection() {
+        let (mut view, requests) = view(1);
+        for channel in ["#a", "#b", "#c"] {
+            view.send_request(twitch::Request::PartChannel {
+                channel: channel.into(),
+            });
+        }
+        // only the first fit, the rest are held rather than blocking the view
+        assert_eq!(view.blocked_requests.len(), 2);
+        view.flush_blocked_requests();
+        assert_eq!(*view.state.send_busy, " | send queue busy (2)");
+
+        assert_eq!(parted(requests.try_recv().unwrap()), "#a");
+        view.flush_blocked_requests();
+        assert_eq!(view.blocked_requests.len(), 1);
+        assert_eq!(*view.state.send_busy, " | send queue busy (1)");
+
+        // new requests queue up behind the held ones
+        view.send_request(twitch::Request::PartChannel {
+            channel: "#d".into(),
+        });
+        for expected in ["#b", "#c", "#d"] {
+            assert_eq!(parted(requests.try_recv().unwrap()), expected);
+            view.flush_blocked_requests();
+        }
+        assert!(view.blocked_requests.is_empty());
+        assert_eq!(*view.state.send_busy, "");
+    }
+
+    #[test]
+    fn the_active_tab_is_restored_once() {
+        let (mut view, responses, _) = restoring("#b", &["#a", "#b", "#c"]);
+        for channel in ["#a", "#b", "#c"] {
+            join(&responses, channel);
+        }
+        view.tick();
+        assert_eq!(view.tabs.active_name(), Some("#b"));
+        assert!(view.restore_active.is_none());
+
+        // it's done, so later tabs are free to take the focus
+        join(&responses, "#d");
+        view.tick();
+        assert_eq!(view.tabs.active_name(), Some("#d"));
+    }
+
+    #[test]
+    fn picking_a_tab_stops_the_restore() {
+        let (mut view, responses, _) = restoring("#a", &["#a", "#b"]);
+        join(&responses, "#a");
+        join(&responses, "#b");
+        view.tick();
+        assert_eq!(view.tabs.active_name(), Some("#a"));
+
+        responses
+            .try_send(twitch::Response::Disconnected {
+                reason: twitch::DisconnectReason::ServerClosed,
+            })
+            .unwrap();
+        view.tick();
+        assert!(view.restore_active.is_some());
+        view.open_whisper("someone");
+        assert!(view.restore_active.is_none());
+
+        // the channels coming back don't take the user away from the tab they picked
+        responses
+            .try_send(twitch::Response::Connected { user: None })
+            .unwrap();
+        join(&responses, "#a");
+        join(&responses, "#b");
+        view.tick();
+        assert_eq!(view.tabs.active_name(), Some("@someone"));
+    }
+
+    #[test]
+    fn blank_lines_arent_sent() {
+        let (mut view, responses, requests) = restoring("#a", &["#a"]);
+        join(&responses, "#a");
+        view.tick();
+        for data in ["", "   ", "\t", " \n\t\n  "] {
+            view.send_lines(data.into());
+        }
+        assert!(requests.try_recv().is_err());
+
+        // only the blank lines are dropped
+        view.send_lines(String::from("  \nhello  \n\t"));
+        match requests.try_recv() {
+            Ok(twitch::Request::SendMesage { channel, data }) => {
+                assert_eq!((&*channel, &*data), ("#a", "hello"));
+            }
+            _ => panic!("expected a message"),
+        }
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn sends_stop_at_the_rate_limit() {
+        let (mut view, responses, requests) = restoring("#a", &["#a"]);
+        join(&responses, "#a");
+        view.tick();
+
+        let lines = (0..25).map(|i| format!("line {i}")).collect::<Vec<_>>();
+        view.send_lines(lines.join("\n"));
+        assert_eq!(requests.len(), twitch::RateLimit::CAPACITY);
+        // what's left waits in the input
+        assert_eq!(
+            *view.state.input,
+            lines[twitch::RateLimit::CAPACITY..].join("\n")
+        );
+    }
+
+    #[test]
+    fn queued_messages_wait_on_the_rate_limit() {
+        let (mut view, responses, requests) = restoring("#a", &["#a"]);
+        join(&responses, "#a");
+        view.tick();
+        responses
+            .try_send(twitch::Response::Disconnected {
+                reason: twitch::DisconnectReason::ServerClosed,
+            })
+            .unwrap();
+        view.tick();
+
+        let lines = (0..25).map(|i| format!("line {i}")).collect::<Vec<_>>();
+        view.send_lines(lines.join("\n"));
+        assert!(requests.is_empty());
+        assert_eq!(view.offline_queue.len(), lines.len());
+
+        responses
+            .try_send(twitch::Response::Connected { user: None })
+            .unwrap();
+        view.tick();
+        // they go out as the limit allows, not all at once
+        assert_eq!(requests.len(), twitch::RateLimit::CAPACITY);
+        assert_eq!(
+            view.offline_queue.len(),
+            lines.len() - twitch::RateLimit::CAPACITY
+        );
+    }
 }
@@ -0,0 +1,107 @@
+use std::{collections::BTreeMap, path::Path};
+
+use crate::config::Notify;
+
+/// State that is restored on the next run, stored as `key value` lines
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    /// Joined channels, in tab order
+    pub channels: Vec<String>,
+    /// The unread `(messages, mentions)` of channels that had activity
+    pub unread: BTreeMap<String, (usize, usize)>,
+    /// Names shown on the tabs of channels instead of their own
+    pub aliases: BTreeMap<String, String>,
+    /// Which messages ring the bell in channels that don't use the default
+    pub notify: BTreeMap<String, Notify>,
+    /// Whether timestamps are shown, `None` if it was never toggled
+    pub timestamps: Option<bool>,
+    /// The channel that was active when we quit, selected again once it's joined
+    pub active: Option<String>,
+}
+
+impl Session {
+    /// Loads the session from `path`, a missing or unreadable file is an empty session
+    pub fn load(path: &Path) -> Self {
+        let Ok(data) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        let mut this = Self::default();
+        for line in data.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, val) = line.split_once(' ').unwrap_or((line, ""));
+            match (key, val.trim()) {
+                ("channel", channel) if !channel.is_empty() => {
+                    if !this.channels.iter().any(|c| c == channel) {
+                        this.channels.push(channel.to_string())
+                    }
+                }
+                ("alias", val) => {
+                    if let Some((channel, alias)) = val.split_once(' ') {
+                        let alias = alias.trim();
+                        if !alias.is_empty() {
+                            this.aliases.insert(channel.to_string(), alias.to_string());
+                        }
+                    }
+                }
+                ("active", channel) if !channel.is_empty() => {
+                    this.active = Some(channel.to_string())
+                }
+                ("notify", val) => {
+                    let notify = val.split_once(' ').and_then(|(channel, notify)| {
+                        Some((channel, Notify::parse(notify.trim())?))
+                    });
+                    if let Some((channel, notify)) = notify {
+                        this.notify.insert(channel.to_string(), notify);
+                    }
+                }
+                ("timestamps", "on") => this.timestamps = Some(true),
+                ("timestamps", "off") => this.timestamps = Some(false),
+                ("unread", val) => {
+                    let mut parts = val.split_whitespace();
+                    let (Some(channel), Some(messages), Some(mentions)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        continue;
+                    };
+                    if let (Ok(messages), Ok(mentions)) = (messages.parse(), mentions.parse()) {
+                        this.unread
+                            .insert(channel.to_string(), (messages, mentions));
+                    }
+                }
+                _ => {}
+            }
+        }
+        this
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        use std::fmt::Write as _;
+
+        let mut out = String::from("# written by anachat, changes are overwritten\n");
+        for channel in &self.channels {
+            let _ = writeln!(out, "channel {channel}");
+        }
+        for (channel, alias) in &self.aliases {
+            let _ = writeln!(out, "alias {channel} {alias}");
+        }
+        for (channel, notify) in &self.notify {
+            let _ = writeln!(out, "notify {channel} {}", notify.as_str());
+        }
+        for (channel, (messages, mentions)) in &self.unread {
+            let _ = writeln!(out, "unread {channel} {messages} {mentions}");
+        }
+        if let Some(channel) = &self.active {
+            let _ = writeln!(out, "active {channel}");
+        }
+        if let Some(show) = self.timestamps {
+            let _ = writeln!(out, "timestamps {}", if show { "on" } else { "off" });
+        }
+
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use anathema::values::StateValue;
+
+/// A channel listed in the switcher
+#[derive(Debug, anathema::values::State)]
+pub struct SwitcherItem {
+    pub name: StateValue<String>,
+    pub selected: StateValue<bool>,
+}
+
+impl SwitcherItem {
+    pub fn new(name: impl ToString, selected: bool) -> Self {
+        Self {
+            name: StateValue::new(name.to_string()),
+            selected: StateValue::new(selected),
+        }
+    }
+}
+
+/// The fuzzy channel switcher, while it's open
+#[derive(Debug, Default)]
+pub struct Switcher {
+    pub query: String,
+    /// The channels matching the query, best match first
+    pub matches: Vec<String>,
+    pub selected: usize,
+}
+
+impl Switcher {
+    /// Re-filters `names` against the query, ties keep the order they're given in
+    pub fn update<'a>(&mut self, names: impl IntoIterator<Item = &'a str>) {
+        let mut scored = names
+            .into_iter()
+            .filter_map(|name| Some((score(&self.query, name)?, name)))
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+
+        self.matches = scored
+            .into_iter()
+            .map(|(_, name)| name.to_string())
+            .collect();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    /// Moves the selection by `delta`, wrapping around
+    pub fn select(&mut self, delta: isize) {
+        let len = self.matches.len() as isize;
+        if len > 0 {
+            self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.matches.get(self.selected).map(String::as_str)
+    }
+}
+
+/// Scores `name` by how well `query` matches it as a subsequence, ignoring case, higher is better
+///
+/// Consecutive matches and matches at the start of the name (after any `#` or `@`) score higher,
+/// skipped characters lower. `None` if `query` isn't a subsequence of `name`
+pub fn score(query: &str, name: &str) -> Option<i32> {
+    let name = name
+        .trim_start_matches(['#', '@'])
+        .to_lowercase()
+        .chars()
+        .collect::<Vec<_>>();
+
+    let mut score = 0;
+    let mut pos = 0;
+    for (i, ch) in query.to_lowercase().chars().enumerate() {
+        let found = pos + name[pos..].iter().position(|&c| c == ch)?;
+        score += match found {
+            0 => 4,
+            _ if found == pos && i > 0 => 3,
+            _ => 1,
+        };
+        score -= (found - pos) as i32;
+        pos = found + 1;
+    }
+    Some(score)
+}
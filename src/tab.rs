@@ -16,8 +16,11 @@ use crate::geometry::{pos2, Pos2, Rect};
 #[derive(Debug)]
 pub struct Tab {
     text: Value<String>,
+    /// The [`TabRegions::key`] of the view this is drawn in, from the `regions` attribute
+    regions: Value<String>,
     style: WidgetStyle,
     layout: TextLayout,
+    views: Views,
 }
 
 impl Tab {
@@ -31,6 +34,7 @@ impl anathema::core::Widget for Tab {
 
     fn update(&mut self, context: &Context<'_, '_>, node_id: &NodeId) {
         self.text.resolve(context, node_id);
+        self.regions.resolve(context, node_id);
         self.style.resolve(context, node_id);
     }
 
@@ -48,17 +52,14 @@ impl anathema::core::Widget for Tab {
     }
 
     fn paint(&mut self, children: &mut Nodes<'_>, mut ctx: PaintCtx<'_, WithSize>) {
-        let start = ctx.global_pos;
+        let start = pos2(ctx.global_pos.x as _, ctx.global_pos.y as _);
         if let Some(LocalPos { x, y }) =
             ctx.print(self.text.str(), self.style.style(), LocalPos::ZERO)
         {
-            TabRegions::insert(
-                self.text.str(),
-                Rect::from_min_max(
-                    pos2(start.x as _, start.y as _),
-                    pos2(start.x as u16 + x as u16, start.y as u16 + y as u16),
-                ),
-            );
+            let end = start + pos2(x as _, y as _);
+            if let Some(regions) = self.views.get(self.regions.str()) {
+                regions.insert(self.text.str(), Rect::from_min_max(start, end));
+            }
         }
 
         for (widget, children) in children.iter_mut() {
@@ -70,7 +71,27 @@ impl anathema::core::Widget for Tab {
     fn position(&mut self, _children: &mut Nodes<'_>, _ctx: PositionCtx) {}
 }
 
-pub struct TabFactory;
+/// Makes the [`Tab`]s of every view
+///
+/// Factories are registered once for the whole process, so each tab finds the regions it draws
+/// into by the key its template passes as `regions`, and views don't share any
+#[derive(Clone, Default)]
+pub struct TabFactory {
+    views: Views,
+}
+
+impl TabFactory {
+    /// Regions of their own for a view, its template names them with [`TabRegions::key`]
+    pub fn regions(&self) -> TabRegions {
+        let views = &mut *self.views.map.lock().unwrap();
+        let regions = TabRegions {
+            key: Arc::new(format!("regions-{}", views.len())),
+            ..TabRegions::default()
+        };
+        views.push(regions.clone());
+        regions
+    }
+}
 
 impl WidgetFactory for TabFactory {
     fn make(&self, mut ctx: FactoryContext<'_>) -> Result<Box<dyn AnyWidget>> {
@@ -78,45 +99,59 @@ impl WidgetFactory for TabFactory {
             style: ctx.style(),
             layout: TextLayout::new(Size::ZERO, false, Wrap::Normal),
             text: ctx.text.take(),
+            regions: ctx.get("regions"),
+            views: self.views.clone(),
         };
 
         Ok(Box::new(widget))
     }
 }
 
-#[derive(Default)]
-pub struct TabRegions {
-    map: Vec<(Rect, Arc<String>)>,
+/// The regions handed out by a [`TabFactory`], looked up by their key
+#[derive(Clone, Debug, Default)]
+struct Views {
+    map: Arc<Mutex<Vec<TabRegions>>>,
 }
 
-static REGIONS: Mutex<TabRegions> = Mutex::new(TabRegions { map: Vec::new() });
+impl Views {
+    fn get(&self, key: &str) -> Option<TabRegions> {
+        let views = &*self.map.lock().unwrap();
+        views.iter().find(|regions| regions.key() == key).cloned()
+    }
+}
+
+/// Where each tab was last drawn, by its label, so clicks can be mapped back to tabs
+///
+/// This is a handle, clones share the same regions: the factory keeps one for the [`Tab`]s drawn
+/// into it, and the view that owns the tabs keeps another to look clicks up in
+#[derive(Clone, Debug, Default)]
+pub struct TabRegions {
+    key: Arc<String>,
+    map: Arc<Mutex<Vec<(Rect, Arc<String>)>>>,
+}
 
 impl TabRegions {
-    pub fn insert(name: &str, rect: Rect) {
-        let g = &mut *REGIONS.lock().unwrap();
-        if let Some(pos) = g.map.iter().position(|(_, v)| &**v == name) {
-            g.map[pos].0 = rect;
-        } else {
-            g.map.push((rect, Arc::new(name.to_string())))
-        }
+    /// What the view's template passes to its `tab` widgets as `regions`
+    pub fn key(&self) -> &str {
+        &self.key
     }
 
-    pub fn get_all() -> Vec<(Rect, Arc<String>)> {
-        let g = &*REGIONS.lock().unwrap();
-        g.map.clone()
+    pub fn insert(&self, name: &str, rect: Rect) {
+        let map = &mut *self.map.lock().unwrap();
+        if let Some(pos) = map.iter().position(|(_, v)| &**v == name) {
+            map[pos].0 = rect;
+        } else {
+            map.push((rect, Arc::new(name.to_string())))
+        }
     }
 
-    pub fn containing_point(pos: Pos2) -> Option<Arc<String>> {
-        let g = &*REGIONS.lock().unwrap();
-        g.map
-            .iter()
-            .find_map(|(k, v)| k.contains(pos).then(|| Arc::clone(&v)))
+    pub fn clear(&self) {
+        self.map.lock().unwrap().clear();
     }
 
-    pub fn get(rect: Rect) -> Option<Arc<String>> {
-        let g = &*REGIONS.lock().unwrap();
-        g.map
-            .iter()
-            .find_map(|(k, v)| (*k == rect).then(|| Arc::clone(&v)))
+    pub fn containing_point(&self, pos: Pos2) -> Option<Arc<String>> {
+        let map = &*self.map.lock().unwrap();
+        map.iter()
+            .find_map(|(k, v)| k.contains(pos).then(|| Arc::clone(v)))
     }
 }
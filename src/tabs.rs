@@ -1,11 +1,21 @@
 use anathema::values::List;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{channel::Channel, display_channel::DisplayChannel, root_view::RootState};
+use crate::{
+    channel::Channel, config::Palette, display_channel::DisplayChannel, model::AnaMessage,
+    root_view::RootState,
+};
 
 #[derive(Debug, Default)]
 pub struct Tabs {
     pub channels: Vec<Channel>,
     pub active: usize,
+    /// The index of the first visible tab in the tab strip
+    pub scroll: usize,
+    /// The order tabs are kept in, including channels we haven't (re-)joined yet
+    pub order: Vec<String>,
+    /// The colors new tabs are drawn with
+    pub palette: Palette,
 }
 
 impl Tabs {
@@ -17,6 +27,36 @@ impl Tabs {
         self.channels.get_mut(self.active)
     }
 
+    /// The name of the active channel, if there are any
+    pub fn active_name(&self) -> Option<&str> {
+        self.active().map(|channel| channel.name.as_str())
+    }
+
+    /// The open channels, in tab order
+    pub fn channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channels.iter()
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.iter().find(|channel| channel.name == name)
+    }
+
+    /// The messages kept for `name`, oldest first
+    ///
+    /// The active channel's messages are moved into the output while it's active, so they're read
+    /// from `active` instead
+    pub fn messages<'a>(
+        &'a self,
+        name: &str,
+        active: impl IntoIterator<Item = &'a AnaMessage>,
+    ) -> Option<Vec<&'a AnaMessage>> {
+        if self.active_name() == Some(name) {
+            return Some(active.into_iter().collect());
+        }
+        self.channel(name)
+            .map(|channel| channel.messages.iter().collect())
+    }
+
     pub fn next_channel(&mut self, display: &mut List<DisplayChannel>) {
         if self.channels.is_empty() {
             return;
@@ -71,32 +111,71 @@ impl Tabs {
         if self.channels.iter().any(|c| c.name == channel) {
             return;
         }
-        let old = self.active;
-        let len = self.channels.len();
-        self.channels.push(Channel::new(channel));
-        self.active = len;
 
-        let len = state.channels.len();
-        let mut found = false;
-        for i in 0..len {
-            found &= *state.channels[i].name == channel
+        let index = self.insertion_index(channel);
+        let mut old = self.active;
+        if index <= old && !self.channels.is_empty() {
+            old += 1;
         }
 
-        if !found {
-            for i in 0..state.channels.len() {
-                if state.channels[i].is_active() {
-                    state.channels[i].set_inactive();
-                }
+        self.channels.insert(index, Channel::new(channel));
+        self.active = index;
+
+        for i in 0..state.channels.len() {
+            if state.channels[i].is_active() {
+                state.channels[i].set_inactive();
             }
-            state.channels.push_back(DisplayChannel::new(channel));
+        }
+        state
+            .channels
+            .insert(index, DisplayChannel::new(channel, self.palette));
+
+        if !self.order.iter().any(|c| c == channel) {
+            self.order.push(channel.to_string());
         }
 
         self.redraw_messages(old, state);
     }
 
+    /// Re-opens a closed tab for a channel we're still joined to, without taking focus
+    pub fn reopen_channel(&mut self, channel: &str, state: &mut RootState) {
+        if self.channels.is_empty() {
+            return self.join_channel(channel, state);
+        }
+
+        let index = self.insertion_index(channel);
+        if index <= self.active {
+            self.active += 1;
+        }
+
+        let mut tab = Channel::new(channel);
+        tab.set_inactive();
+        self.channels.insert(index, tab);
+
+        let mut display = DisplayChannel::new(channel, self.palette);
+        display.set_inactive();
+        state.channels.insert(index, display);
+    }
+
+    /// Where `channel` goes so the tabs follow [`Self::order`], unknown channels go at the end
+    fn insertion_index(&self, channel: &str) -> usize {
+        let rank = |name: &str| self.order.iter().position(|c| c == name);
+        let Some(rank) = rank(channel) else {
+            return self.channels.len();
+        };
+
+        self.channels
+            .iter()
+            .position(|c| rank(&c.name).map_or(true, |other| other > rank))
+            .unwrap_or(self.channels.len())
+    }
+
+    /// Removes the tab for `channel`, this doesn't leave the channel
     pub fn part_channel(&mut self, channel: &str, state: &mut RootState) {
+        let mut was_active = false;
         if let Some(pos) = self.channels.iter().position(|c| c.name == channel) {
-            if self.active == pos {
+            was_active = self.active == pos;
+            if self.active >= pos {
                 self.active = self.active.saturating_sub(1);
             }
             self.channels.remove(pos);
@@ -118,14 +197,88 @@ impl Tabs {
             }
         }
 
-        while state.output.pop_front().is_some() {}
-        self.synchronize_input_buffer(state);
+        // the output belongs to the active channel, so keep it unless that's the one that left
+        if was_active {
+            while state.output.pop_front().is_some() {}
+            self.synchronize_input_buffer(state);
+        }
+    }
+
+    /// Numbers the tabs by their Ctrl+number shortcut, as [`Tabs::switch_to_channel`] indexes them
+    ///
+    /// The first nine are `1:` to `9:`, the tenth is `0:` and the rest can't be reached that way
+    pub fn update_numbers(display: &mut List<DisplayChannel>, numbered: bool) {
+        for i in 0..display.len() {
+            let number = match i {
+                _ if !numbered => String::new(),
+                0..=8 => format!("{}:", i + 1),
+                9 => String::from("0:"),
+                _ => String::new(),
+            };
+            if *display[i].number != number {
+                *display[i].number = number;
+            }
+        }
+    }
+
+    /// Shifts the visible window of tabs so the active one fits within `width` columns
+    ///
+    /// Returns whether tabs are clipped on the (left, right)
+    pub fn update_scroll(
+        &mut self,
+        display: &mut List<DisplayChannel>,
+        width: usize,
+    ) -> (bool, bool) {
+        // each tab is followed by a space
+        let widths = (0..display.len())
+            .map(|i| {
+                let tab = &display[i];
+                tab.number.width()
+                    + tab.marker.width()
+                    + tab.label.width()
+                    + tab.modes.width()
+                    + tab.unread.width()
+                    + 1
+            })
+            .collect::<Vec<_>>();
+
+        if widths.is_empty() {
+            self.scroll = 0;
+            return (false, false);
+        }
+
+        let active = self.active.min(widths.len() - 1);
+        self.scroll = self.scroll.min(active);
+        while self.scroll < active && widths[self.scroll..=active].iter().sum::<usize>() > width {
+            self.scroll += 1;
+        }
+
+        let mut used = 0;
+        let mut end = self.scroll;
+        while end < widths.len() && (used + widths[end] <= width || end == self.scroll) {
+            used += widths[end];
+            end += 1;
+        }
+
+        for (i, _) in widths.iter().enumerate() {
+            let visible = (self.scroll..end).contains(&i);
+            if *display[i].visible != visible {
+                *display[i].visible = visible;
+            }
+        }
+
+        (self.scroll > 0, end < widths.len())
     }
 
     pub fn find_index_by_name(&self, name: &str) -> Option<usize> {
         self.channels.iter().position(|c| c.name == name)
     }
 
+    /// Finds a tab by what it shows, which is its alias if it has one
+    pub fn find_index_by_label(&self, label: &str) -> Option<usize> {
+        self.channels.iter().position(|c| c.label() == label)
+    }
+
     pub fn redraw_messages(&mut self, old: usize, state: &mut RootState) {
         if self.active == old {
             return;
@@ -134,7 +287,7 @@ impl Tabs {
         if let Some(channel) = self.channels.get_mut(old) {
             channel.buffer.replace(std::mem::take(&mut *state.input));
             while let Some(mut msg) = state.output.pop_front() {
-                channel.messages.push(std::mem::take(&mut msg))
+                channel.messages.push_back(std::mem::take(&mut msg))
             }
         }
 
@@ -150,3 +303,71 @@ impl Tabs {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tabs_with(names: &[&str]) -> (Tabs, RootState) {
+        let mut tabs = Tabs::default();
+        let mut state = RootState::default();
+        for name in names {
+            tabs.join_channel(name, &mut state);
+            state
+                .output
+                .push_back(AnaMessage::system(name, format!("in {name}")));
+        }
+        (tabs, state)
+    }
+
+    fn output(state: &RootState) -> Vec<String> {
+        (0..state.output.len())
+            .map(|i| state.output[i].data.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn closing_an_inactive_tab_keeps_the_active_output() {
+        let (mut tabs, mut state) = tabs_with(&["#foo", "#bar"]);
+        assert_eq!(tabs.active_name(), Some("#bar"));
+
+        tabs.part_channel("#foo", &mut state);
+
+        assert_eq!(tabs.active_name(), Some("#bar"));
+        assert_eq!(output(&state), ["in #bar"]);
+    }
+
+    #[test]
+    fn closing_the_active_tab_shows_the_one_before_it() {
+        let (mut tabs, mut state) = tabs_with(&["#foo", "#bar"]);
+
+        tabs.part_channel("#bar", &mut state);
+
+        assert_eq!(tabs.active_name(), Some("#foo"));
+        assert_eq!(output(&state), ["in #foo"]);
+    }
+
+    #[test]
+    fn switching_between_channels_and_pseudo_tabs() {
+        let (mut tabs, mut state) = tabs_with(&["#foo", "@someone", "#bar"]);
+
+        tabs.switch_to_channel(1, &mut state.channels);
+        tabs.redraw_messages(2, &mut state);
+        assert_eq!(tabs.active_name(), Some("@someone"));
+        assert_eq!(output(&state), ["in @someone"]);
+        assert!(state.channels[1].is_active());
+        assert!(!state.channels[2].is_active());
+
+        tabs.next_channel(&mut state.channels);
+        assert_eq!(tabs.active_name(), Some("#bar"));
+        tabs.next_channel(&mut state.channels);
+        assert_eq!(tabs.active_name(), Some("#foo"));
+        tabs.previous_channel(&mut state.channels);
+        assert_eq!(tabs.active_name(), Some("#bar"));
+
+        // there's no tab there, so nothing changes
+        tabs.switch_to_channel(3, &mut state.channels);
+        assert_eq!(tabs.active_name(), Some("#bar"));
+        assert!(state.channels[2].is_active());
+    }
+}
@@ -0,0 +1,115 @@
+use chrono::{format::StrftimeItems, DateTime, FixedOffset, Local, TimeZone, Utc};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Zone {
+    #[default]
+    Local,
+    Utc,
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    /// Parses `local`, `utc` or a fixed offset like `+02:00`, `-0530` or `+9`
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if input.eq_ignore_ascii_case("local") {
+            return Some(Self::Local);
+        }
+        if input.eq_ignore_ascii_case("utc") || input.eq_ignore_ascii_case("z") {
+            return Some(Self::Utc);
+        }
+
+        let (sign, rest) = match input.as_bytes().first()? {
+            b'+' => (1, &input[1..]),
+            b'-' => (-1, &input[1..]),
+            _ => return None,
+        };
+
+        let (hours, minutes) = match rest.split_once(':') {
+            Some((h, m)) => (h, m),
+            None if rest.len() == 4 => rest.split_at(2),
+            None => (rest, "0"),
+        };
+
+        let hours = hours.parse::<i32>().ok().filter(|h| *h <= 23)?;
+        let minutes = minutes.parse::<i32>().ok().filter(|m| *m <= 59)?;
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Self::Fixed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TimestampFormat {
+    format: String,
+    zone: Zone,
+    /// Show how long ago a message was sent, e.g. `5m ago`, rather than when
+    pub relative: bool,
+    /// Leave the timestamps off messages
+    pub hidden: bool,
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        Self {
+            format: String::from(Self::DEFAULT_FORMAT),
+            zone: Zone::Local,
+            relative: false,
+            hidden: false,
+        }
+    }
+}
+
+impl TimestampFormat {
+    pub const DEFAULT_FORMAT: &'static str = "%H:%M";
+
+    /// Creates a new format, falling back to [`Self::DEFAULT_FORMAT`] if `format` isn't a valid strftime string
+    pub fn new(format: &str, zone: Zone, relative: bool) -> Self {
+        let format = if Self::is_valid(format) {
+            format
+        } else {
+            Self::DEFAULT_FORMAT
+        };
+
+        Self {
+            format: format.to_string(),
+            zone,
+            relative,
+            hidden: false,
+        }
+    }
+
+    pub fn is_valid(format: &str) -> bool {
+        !format.is_empty()
+            && StrftimeItems::new(format).all(|item| !matches!(item, chrono::format::Item::Error))
+    }
+
+    pub fn format(&self, ts: DateTime<Utc>) -> String {
+        match self.zone {
+            Zone::Local => ts.with_timezone(&Local).format(&self.format).to_string(),
+            Zone::Utc => ts.format(&self.format).to_string(),
+            Zone::Fixed(offset) => ts.with_timezone(&offset).format(&self.format).to_string(),
+        }
+    }
+
+    pub fn format_millis(&self, millis: i64) -> String {
+        let Some(ts) = Utc.timestamp_millis_opt(millis).single() else {
+            return String::new();
+        };
+
+        if self.relative {
+            relative(ts, Utc::now())
+        } else {
+            self.format(ts)
+        }
+    }
+}
+
+/// A coarse description of how long before `now` that `ts` was, e.g. `now`, `5m ago`
+pub fn relative(ts: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - ts).num_seconds().max(0);
+    match secs {
+        0..=59 => String::from("now"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
@@ -2,39 +2,320 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet, VecDeque},
     future::Future,
+    path::{Path, PathBuf},
     task::Poll,
+    time::{Duration, Instant},
 };
 
 use smol::{
     channel::{Receiver, Sender},
     future::FutureExt,
-    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+};
+use twitch_message::{
+    encode::{Capability, Encode},
+    messages::MessageKind,
 };
-use twitch_message::{encode::Encode, messages::MessageKind};
 
 pub enum Request {
-    JoinChannel { channel: String },
-    PartChannel { channel: String },
-    SendMesage { channel: String, data: String },
-    Disconnect { reconnect: bool },
+    /// Joins all of `channels` in a single write
+    JoinChannels {
+        channels: Vec<String>,
+    },
+    PartChannel {
+        channel: String,
+    },
+    SendMesage {
+        channel: String,
+        data: String,
+    },
+    /// Closes the connection, and with `reconnect` opens it again and re-joins every channel
+    ///
+    /// There's a single connection for the single identity, so this affects every tab
+    Disconnect {
+        reconnect: bool,
+    },
+    /// Disconnects until [`Request::Resume`], requested channels are joined once we're back
+    Pause,
+    Resume,
 }
 
 #[derive(Debug)]
 pub enum Response {
     Connecting,
-    Connected { user: User },
-    JoinChannel { channel: String },
-    PartChannel { channel: String },
-    Message { message: Message },
-    Disconnected,
+    /// `user` is `None` if the server didn't tell us who we are, chat is then read-only
+    Connected {
+        user: Option<User>,
+    },
+    JoinChannel {
+        channel: String,
+    },
+    PartChannel {
+        channel: String,
+    },
+    /// Someone else joined (or left) a channel, only sent with the `membership` capability
+    Membership {
+        channel: String,
+        joined: bool,
+    },
+    /// A chat message, or a whisper if its channel is `@user`
+    Message {
+        message: Message,
+    },
+    RoomState {
+        channel: String,
+        state: RoomState,
+    },
+    UserState {
+        channel: String,
+        roles: Roles,
+    },
+    Notice {
+        channel: Option<String>,
+        message: String,
+    },
+    /// `channel` started hosting `target` (with `viewers` if twitch said), or stopped if it's `None`
+    Host {
+        channel: String,
+        target: Option<String>,
+        viewers: Option<u32>,
+    },
+    Disconnected {
+        reason: DisconnectReason,
+    },
+    /// The server asked us to reconnect, which we'll do right away, re-joining our channels
+    ServerReconnect,
+    /// A moderator deleted the message with `id`
+    ClearMessage {
+        channel: String,
+        id: String,
+    },
+    /// Something was read from the server, sent at most every [`HEARTBEAT_INTERVAL`]
+    Heartbeat,
+    /// We sent a chat message, which counts against twitch's limit, or twitch said we hit it
+    RateLimit {
+        limit: RateLimit,
+    },
+    /// We're waiting `delay` before the `attempt`th try at reconnecting, counting from 1
+    Reconnecting {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// We stopped reconnecting after too many attempts, nothing else will be sent
+    GaveUp {
+        attempts: u32,
+    },
     AuthenticationFailed,
 }
 
+/// Why the connection ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// We couldn't connect
+    Network,
+    /// The server closed the connection
+    ServerClosed,
+    /// We quit, nothing else will be sent
+    Quit,
+    /// We, or the server, asked to reconnect
+    Reconnect,
+    /// We disconnected after being idle, and wait to be resumed
+    Idle,
+    /// Every line of the replay was read, reconnecting starts it over
+    ReplayEnded,
+    Error(String),
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Network => f.write_str("network error"),
+            Self::ServerClosed => f.write_str("server closed the connection"),
+            Self::Quit => f.write_str("quit"),
+            Self::Reconnect => f.write_str("reconnecting"),
+            Self::Idle => f.write_str("idle"),
+            Self::ReplayEnded => f.write_str("end of replay"),
+            Self::Error(err) => f.write_str(err),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Message {
     pub sender: User,
     pub channel: String,
     pub data: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// The sender's roles in the channel
+    pub roles: Roles,
+    /// The server's id for the message, used to delete or reply to it
+    pub id: Option<String>,
+    /// All of the line's (unescaped) tags, for anything that doesn't have its own field
+    pub tags: HashMap<String, String>,
+    /// Whether this is a `/me` action, `data` is then just the action's text
+    pub action: bool,
+}
+
+impl Message {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.get(name).map(String::as_str)
+    }
+}
+
+/// A (possibly partial) ROOMSTATE update, `None` fields were not part of the update
+#[derive(Clone, Debug, Default)]
+pub struct RoomState {
+    pub emote_only: Option<bool>,
+    /// Minutes someone must have followed for, `-1` if disabled
+    pub followers_only: Option<i64>,
+    pub subs_only: Option<bool>,
+    /// Seconds between messages, `0` if disabled
+    pub slow: Option<u64>,
+    /// The channel's id, which messages from a shared chat refer to their channel by
+    pub room_id: Option<String>,
+}
+
+impl RoomState {
+    fn from_raw(raw: &str) -> Self {
+        let mut state = Self::default();
+        for (key, val) in tags(raw) {
+            match key {
+                "emote-only" => state.emote_only = Some(val == "1"),
+                "followers-only" => state.followers_only = val.parse().ok(),
+                "subs-only" => state.subs_only = Some(val == "1"),
+                "slow" => state.slow = val.parse().ok(),
+                "room-id" if !val.is_empty() => state.room_id = Some(val.to_string()),
+                _ => {}
+            }
+        }
+        state
+    }
+
+    /// Short descriptions of the restrictions that are enabled, e.g. `slow 30s`
+    pub fn modes(&self) -> Vec<String> {
+        let mut modes = vec![];
+        if let Some(slow) = self.slow.filter(|&slow| slow > 0) {
+            modes.push(format!("slow {slow}s"));
+        }
+        if self.subs_only == Some(true) {
+            modes.push(String::from("sub-only"));
+        }
+        if self.emote_only == Some(true) {
+            modes.push(String::from("emote-only"));
+        }
+        match self.followers_only {
+            Some(0) => modes.push(String::from("followers-only")),
+            Some(minutes) if minutes > 0 => modes.push(format!("followers-only {minutes}m")),
+            _ => {}
+        }
+        modes
+    }
+
+    /// Glyphs for the restrictions that are enabled, e.g. ` 🔒😀`, or with `ascii` ` $:)`
+    pub fn glyphs(&self, ascii: bool) -> String {
+        let mut glyphs = String::new();
+        for (enabled, glyph, fallback) in [
+            (self.subs_only == Some(true), "🔒", "$"),
+            (self.emote_only == Some(true), "😀", ":)"),
+            (self.followers_only.is_some_and(|m| m >= 0), "💜", "+"),
+        ] {
+            if enabled {
+                glyphs.push_str(if ascii { fallback } else { glyph });
+            }
+        }
+        if !glyphs.is_empty() {
+            glyphs.insert(0, ' ');
+        }
+        glyphs
+    }
+
+    pub fn merge(&mut self, update: Self) {
+        self.emote_only = update.emote_only.or(self.emote_only);
+        self.followers_only = update.followers_only.or(self.followers_only);
+        self.subs_only = update.subs_only.or(self.subs_only);
+        self.slow = update.slow.or(self.slow);
+        self.room_id = update.room_id.or(self.room_id.take());
+    }
+}
+
+/// What a NOTICE's `msg-id` tells us, beyond the text that's shown
+#[derive(Debug)]
+enum NoticeHint {
+    /// We sent messages too quickly, whatever our own count says
+    RateLimited,
+    /// A restriction rejected our message, which we may have missed the ROOMSTATE for
+    RoomState(RoomState),
+}
+
+impl NoticeHint {
+    /// `None` for the notices that are only shown
+    fn from_msg_id(msg_id: &str) -> Option<Self> {
+        let state = match msg_id {
+            "msg_ratelimit" => return Some(Self::RateLimited),
+            // how long someone must have followed for isn't part of the notice
+            "msg_followersonly" | "msg_followersonly_zero" | "msg_followersonly_followed" => {
+                RoomState {
+                    followers_only: Some(0),
+                    ..RoomState::default()
+                }
+            }
+            "msg_subsonly" => RoomState {
+                subs_only: Some(true),
+                ..RoomState::default()
+            },
+            "msg_emoteonly" => RoomState {
+                emote_only: Some(true),
+                ..RoomState::default()
+            },
+            _ => return None,
+        };
+        Some(Self::RoomState(state))
+    }
+}
+
+/// Our roles in a channel, derived from its badges
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Roles {
+    pub broadcaster: bool,
+    pub moderator: bool,
+    pub vip: bool,
+    pub subscriber: bool,
+}
+
+impl Roles {
+    fn from_raw(raw: &str) -> Self {
+        let badges = tag(raw, "badges").unwrap_or_default();
+
+        let mut roles = Self::default();
+        for badge in badges.split(',') {
+            match badge.split_once('/').map_or(badge, |(name, _)| name) {
+                "broadcaster" => roles.broadcaster = true,
+                "moderator" => roles.moderator = true,
+                "vip" => roles.vip = true,
+                "subscriber" | "founder" => roles.subscriber = true,
+                _ => {}
+            }
+        }
+        roles
+    }
+
+    /// The names of the roles, e.g. `["moderator", "subscriber"]`
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.broadcaster, "broadcaster"),
+            (self.moderator, "moderator"),
+            (self.vip, "vip"),
+            (self.subscriber, "subscriber"),
+        ]
+        .into_iter()
+        .filter_map(|(has, name)| has.then_some(name))
+        .collect()
+    }
+
+    /// Broadcasters and moderators are exempt from the room's chat restrictions
+    pub const fn is_privileged(&self) -> bool {
+        self.broadcaster || self.moderator
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -44,6 +325,81 @@ pub struct User {
     pub name: String,
 }
 
+/// Twitch's limit on how many chat messages we can send, counted over a sliding window
+///
+/// Messages sent over the limit get us locked out of chat for a while, so it's shown while typing and
+/// nothing more is sent once it's used up
+#[derive(Clone, Debug, Default)]
+pub struct RateLimit {
+    /// When the messages still counting against the limit were sent, oldest first
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimit {
+    /// How many messages can be sent per [`RateLimit::WINDOW`]
+    pub const CAPACITY: usize = 20;
+    /// How many can be sent in channels where we're the broadcaster, a moderator or a vip
+    pub const ELEVATED_CAPACITY: usize = 100;
+    pub const WINDOW: Duration = Duration::from_secs(30);
+
+    /// The limit in a channel we have `roles` in, `None` if the server hasn't told us yet
+    pub fn capacity(roles: Option<&Roles>) -> usize {
+        match roles {
+            Some(roles) if roles.broadcaster || roles.moderator || roles.vip => {
+                Self::ELEVATED_CAPACITY
+            }
+            _ => Self::CAPACITY,
+        }
+    }
+
+    pub fn record(&mut self) {
+        let now = Instant::now();
+        while self.sent.len() >= Self::ELEVATED_CAPACITY
+            || self
+                .sent
+                .front()
+                .is_some_and(|&sent| now.duration_since(sent) >= Self::WINDOW)
+        {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(now);
+    }
+
+    /// Uses up what's left of the limit, for when twitch says we've hit it regardless
+    fn exhaust(&mut self) {
+        while self.remaining(Self::ELEVATED_CAPACITY) > 0 {
+            self.record();
+        }
+    }
+
+    /// How many of the recorded messages still count against the limit
+    fn used(&self) -> usize {
+        self.sent
+            .iter()
+            .filter(|sent| sent.elapsed() < Self::WINDOW)
+            .count()
+    }
+
+    /// How many more messages can be sent right now, with a limit of `capacity`
+    pub fn remaining(&self, capacity: usize) -> usize {
+        capacity.saturating_sub(self.used())
+    }
+
+    /// How long until another message can be sent with a limit of `capacity`, `None` if one can be
+    /// sent now
+    pub fn next_available(&self, capacity: usize) -> Option<Duration> {
+        if self.remaining(capacity) > 0 {
+            return None;
+        }
+        // the newest `capacity - 1` can stay, the one before them has to age out first
+        let sent = self.sent.get(self.sent.len().checked_sub(capacity)?)?;
+        Some(Self::WINDOW.saturating_sub(sent.elapsed()))
+    }
+}
+
+/// How often [`Response::Heartbeat`] is sent, while lines are being read
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub fn connect(
     config: Config,
     req: Receiver<Request>,
@@ -52,63 +408,112 @@ pub fn connect(
     let addr = twitch_message::TWITCH_IRC_ADDRESS;
 
     smol::block_on::<anyhow::Result<()>>(async move {
-        let mut requested_channels = HashSet::<String>::new();
+        let mut requested_channels = config.channels.iter().cloned().collect::<HashSet<_>>();
+        // connections that failed or dropped since we last registered
+        let mut failures = 0;
+        // twitch keeps counting across reconnects
+        let mut rate_limit = RateLimit::default();
 
         'outer: loop {
+            if failures > 0 {
+                if config.max_reconnects.is_some_and(|max| failures > max) {
+                    let attempts = failures - 1;
+                    let _ = resp.send(Response::GaveUp { attempts }).await;
+                    anyhow::bail!("gave up reconnecting after {attempts} attempts");
+                }
+
+                let delay = config.reconnect_delay;
+                let reconnecting = Response::Reconnecting {
+                    attempt: failures,
+                    delay,
+                };
+                if resp.send(reconnecting).await.is_err() {
+                    break 'outer;
+                }
+                smol::Timer::after(delay).await;
+            }
+
             if resp.send(Response::Connecting).await.is_err() {
                 break 'outer;
             }
 
-            let Ok(stream) = smol::net::TcpStream::connect(addr).await else {
-                if resp.send(Response::Disconnected).await.is_err() {
-                    break 'outer;
+            let (read, write) = match &config.replay {
+                Some(path) => match Replay::open(path) {
+                    Ok((replay, sent)) => (
+                        Box::new(smol::Unblock::new(replay)) as Box<dyn AsyncRead + Unpin>,
+                        Box::new(smol::Unblock::new(sent)) as Box<dyn AsyncWrite + Unpin>,
+                    ),
+                    Err(err) => {
+                        let err = format!("cannot open replay '{}': {err}", path.display());
+                        let reason = DisconnectReason::Error(err.clone());
+                        let _ = resp.send(Response::Disconnected { reason }).await;
+                        anyhow::bail!(err);
+                    }
+                },
+                None => {
+                    let Ok(stream) = smol::net::TcpStream::connect(addr).await else {
+                        failures += 1;
+                        let reason = DisconnectReason::Network;
+                        if resp.send(Response::Disconnected { reason }).await.is_err() {
+                            break 'outer;
+                        }
+                        continue 'outer;
+                    };
+
+                    let (read, write) = smol::io::split(stream);
+                    (
+                        Box::new(read) as Box<dyn AsyncRead + Unpin>,
+                        Box::new(write) as Box<dyn AsyncWrite + Unpin>,
+                    )
                 }
-
-                smol::Timer::after(std::time::Duration::from_secs(3)).await;
-                continue 'outer;
             };
 
-            let (read, write) = smol::io::split(stream);
-
             let mut reader = Reader::new(read);
             let mut encoder = AsyncEncoder::new(write);
 
-            if register(&config, &mut encoder).await.is_err() {
-                if resp.send(Response::Disconnected).await.is_err() {
+            if let Err(err) = register(&config, &mut encoder).await {
+                failures += 1;
+                let reason = DisconnectReason::Error(err.to_string());
+                if resp.send(Response::Disconnected { reason }).await.is_err() {
                     break 'outer;
                 }
-
-                smol::Timer::after(std::time::Duration::from_secs(3)).await;
                 continue 'outer;
             }
 
-            struct PendingMessage {
-                user: User,
-                data: String,
-            }
-
-            let mut pending_messages = <HashMap<String, VecDeque<PendingMessage>>>::new();
+            let mut pending_messages = PendingMessages::default();
+            // without tags, USERSTATE doesn't have the id we match our messages' echoes with
+            let mut has_tags = false;
+            let mut last_heartbeat = <Option<Instant>>::None;
 
             let mut our_name = <Option<String>>::None;
             let mut our_user = <Option<User>>::None;
 
-            'inner: loop {
+            let reason = 'inner: loop {
                 let read_line = reader.read_line();
                 let recv_req = req.recv();
                 let read_line = std::pin::pin!(read_line);
                 let recv_req = std::pin::pin!(recv_req);
 
                 let line = match select2(read_line, recv_req).await {
-                    Either::Left(Ok(read_line)) => read_line,
+                    Either::Left(Ok(read_line)) => {
+                        if last_heartbeat.map_or(true, |last| last.elapsed() >= HEARTBEAT_INTERVAL)
+                        {
+                            last_heartbeat.replace(Instant::now());
+                            if resp.send(Response::Heartbeat).await.is_err() {
+                                break 'outer;
+                            }
+                        }
+                        read_line
+                    }
                     Either::Right(Ok(recv_req)) => match recv_req {
-                        Request::JoinChannel { channel } => {
-                            let join = twitch_message::encode::join(&channel);
-                            if encoder.encode(join).is_err() {
-                                break 'inner;
+                        Request::JoinChannels { channels } => {
+                            let joins = channels.iter().map(|c| twitch_message::encode::join(c));
+                            if let Err(err) = encoder.encode_all(joins) {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
-                            if encoder.flush().await.is_err() {
-                                break 'inner;
+                            if let Err(err) = encoder.flush().await {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
                             continue 'inner;
@@ -116,12 +521,12 @@ pub fn connect(
 
                         Request::PartChannel { channel } => {
                             let part = twitch_message::encode::part(&channel);
-                            if encoder.encode(part).is_err() {
-                                break 'inner;
+                            if let Err(err) = encoder.encode(part) {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
-                            if encoder.flush().await.is_err() {
-                                break 'inner;
+                            if let Err(err) = encoder.flush().await {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
                             continue 'inner;
@@ -129,20 +534,45 @@ pub fn connect(
 
                         Request::SendMesage { channel, data } => {
                             let msg = twitch_message::encode::privmsg(&channel, &data);
-                            if encoder.encode(msg).is_err() {
-                                break 'inner;
+                            if let Err(err) = encoder.encode(msg) {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
-                            if encoder.flush().await.is_err() {
-                                break 'inner;
+                            if let Err(err) = encoder.flush().await {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
-                            pending_messages.entry(channel).or_default().push_back(
-                                PendingMessage {
-                                    user: our_user.clone().expect("we must be a user"),
-                                    data,
-                                },
-                            );
+                            rate_limit.record();
+                            let limit = rate_limit.clone();
+                            if resp.send(Response::RateLimit { limit }).await.is_err() {
+                                break 'outer;
+                            }
+
+                            // without knowing who we are, the echo can't be shown
+                            let Some(user) = our_user.clone() else {
+                                continue 'inner;
+                            };
+
+                            if has_tags {
+                                pending_messages.push(channel, user, data);
+                                continue 'inner;
+                            }
+
+                            // there won't be an echo, so show it now
+                            let (data, action) = own_action(data);
+                            let message = Message {
+                                sender: user,
+                                channel,
+                                data,
+                                timestamp: chrono::Utc::now(),
+                                roles: Roles::default(),
+                                id: None,
+                                tags: HashMap::new(),
+                                action,
+                            };
+                            if resp.send(Response::Message { message }).await.is_err() {
+                                break 'outer;
+                            }
 
                             continue 'inner;
                         }
@@ -153,18 +583,61 @@ pub fn connect(
                             }
 
                             if !reconnect {
+                                let reason = DisconnectReason::Quit;
+                                let _ = resp.send(Response::Disconnected { reason }).await;
                                 break 'outer;
                             } else {
-                                break 'inner;
+                                break 'inner DisconnectReason::Reconnect;
+                            }
+                        }
+
+                        Request::Pause => {
+                            if encoder.encode(twitch_message::encode::raw("QUIT")).is_ok() {
+                                let _ = encoder.flush().await;
                             }
+                            break 'inner DisconnectReason::Idle;
                         }
+
+                        Request::Resume => continue 'inner,
                     },
 
-                    Either::Left(Err(..)) => break 'inner,
+                    Either::Left(Err(ReadError::Eof)) if config.replay.is_some() => {
+                        break 'inner DisconnectReason::ReplayEnded
+                    }
+                    Either::Left(Err(ReadError::Eof)) => {
+                        break 'inner DisconnectReason::ServerClosed
+                    }
+                    Either::Left(Err(ReadError::Io(err))) => {
+                        break 'inner DisconnectReason::Error(format!("read error: {err}"));
+                    }
                     Either::Right(Err(..)) => break 'outer,
                 };
 
                 for msg in twitch_message::parse_many(&line).flatten() {
+                    if let Some(mut caps) = parse_cap_ack(&msg.raw) {
+                        has_tags |= caps.any(|cap| cap == "twitch.tv/tags");
+                        continue;
+                    }
+
+                    if let Some((channel, target, viewers)) = parse_host_target(&msg.raw) {
+                        let host = Response::Host {
+                            channel,
+                            target,
+                            viewers,
+                        };
+                        if resp.send(host).await.is_err() {
+                            break 'outer;
+                        }
+                        continue;
+                    }
+
+                    if let Some(message) = parse_whisper(&msg.raw) {
+                        if resp.send(Response::Message { message }).await.is_err() {
+                            break 'outer;
+                        }
+                        continue;
+                    }
+
                     use twitch_message::messages::TwitchMessage as M;
                     match msg.as_enum() {
                         #[allow(deprecated)]
@@ -174,14 +647,68 @@ pub fn connect(
                             }
                         }
 
-                        M::Reconnect(_) => break 'inner,
+                        #[allow(deprecated)]
+                        M::Notice(msg) => {
+                            let channel = twitch_message::parse_many(&msg.raw)
+                                .flatten()
+                                .next()
+                                .and_then(|s| s.args.first().map(|s| s.to_string()))
+                                .filter(|s| s.starts_with('#'));
+
+                            // `msg_*` notices mean the server rejected a message we sent
+                            let msg_id = tag(&msg.raw, "msg-id").unwrap_or_default();
+                            let rejected = msg_id.starts_with("msg_");
+                            if let Some(channel) = channel.as_deref().filter(|_| rejected) {
+                                let _ = pending_messages.pop(channel);
+                            }
+
+                            let hint = match NoticeHint::from_msg_id(msg_id) {
+                                Some(NoticeHint::RateLimited) => {
+                                    rate_limit.exhaust();
+                                    let limit = rate_limit.clone();
+                                    Some(Response::RateLimit { limit })
+                                }
+                                Some(NoticeHint::RoomState(state)) => channel
+                                    .clone()
+                                    .map(|channel| Response::RoomState { channel, state }),
+                                None => None,
+                            };
+                            if let Some(hint) = hint {
+                                if resp.send(hint).await.is_err() {
+                                    break 'outer;
+                                }
+                            }
+
+                            let message = msg.message.to_string();
+                            if resp
+                                .send(Response::Notice { channel, message })
+                                .await
+                                .is_err()
+                            {
+                                break 'outer;
+                            }
+                        }
+
+                        M::Reconnect(_) => {
+                            if resp.send(Response::ServerReconnect).await.is_err() {
+                                break 'outer;
+                            }
+                            break 'inner DisconnectReason::Reconnect;
+                        }
 
                         M::Ping(msg) => {
-                            encoder
-                                .encode(twitch_message::encode::pong(&msg.token))
-                                .expect("identity transformation");
-                            if encoder.flush().await.is_err() {
-                                break 'inner;
+                            // a bare PING has no token to echo, and `PONG :` isn't valid
+                            if msg.token.trim().is_empty() {
+                                encoder
+                                    .encode(twitch_message::encode::raw("PONG"))
+                                    .expect("identity transformation");
+                            } else {
+                                encoder
+                                    .encode(twitch_message::encode::pong(&msg.token))
+                                    .expect("identity transformation");
+                            }
+                            if let Err(err) = encoder.flush().await {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
                         }
 
@@ -189,46 +716,113 @@ pub fn connect(
                             let _ = our_name.replace(msg.name.to_string());
                         }
 
-                        M::UserState(msg) if msg.msg_id().is_some() => {
-                            if let Some(data) = twitch_message::parse_many(&msg.raw)
+                        M::UserState(msg) => {
+                            let Some(channel) = twitch_message::parse_many(&msg.raw)
                                 .flatten()
                                 .next()
                                 .and_then(|mut s| s.args.pop())
+                            else {
+                                continue;
+                            };
+
+                            let roles = Roles::from_raw(&msg.raw);
+                            if resp
+                                .send(Response::UserState {
+                                    channel: channel.to_string(),
+                                    roles,
+                                })
+                                .await
+                                .is_err()
                             {
-                                if let Some(queue) = pending_messages.get_mut(&*data) {
-                                    if let Some(msg) = queue.pop_front() {
-                                        let message = Message {
-                                            sender: msg.user,
-                                            channel: data.to_string(),
-                                            data: msg.data,
-                                        };
-                                        if resp.send(Response::Message { message }).await.is_err() {
-                                            break 'outer;
-                                        }
-                                    }
+                                break 'outer;
+                            }
+
+                            // this is also sent on joining, but only has an id for a message we sent
+                            let Some(id) = tag(&msg.raw, "id") else {
+                                continue;
+                            };
+
+                            if let Some(pending) = pending_messages.pop(&channel) {
+                                let (data, action) = own_action(pending.data);
+                                let message = Message {
+                                    sender: pending.user,
+                                    channel: channel.to_string(),
+                                    data,
+                                    timestamp: chrono::Utc::now(),
+                                    roles,
+                                    id: Some(id.to_string()),
+                                    // these describe us, as the sender
+                                    tags: tag_map(&msg.raw),
+                                    action,
+                                };
+                                if resp.send(Response::Message { message }).await.is_err() {
+                                    break 'outer;
                                 }
                             }
                         }
 
+                        M::RoomState(msg) => {
+                            let state = RoomState::from_raw(&msg.raw);
+                            let channel = msg.channel.to_string();
+                            if resp
+                                .send(Response::RoomState { channel, state })
+                                .await
+                                .is_err()
+                            {
+                                break 'outer;
+                            }
+                        }
+
+                        M::ClearMsg(msg) => {
+                            let Some(id) = tag(&msg.raw, "target-msg-id") else {
+                                continue;
+                            };
+
+                            let clear = Response::ClearMessage {
+                                channel: msg.channel.to_string(),
+                                id: id.to_string(),
+                            };
+                            if resp.send(clear).await.is_err() {
+                                break 'outer;
+                            }
+                        }
+
                         M::GlobalUserState(msg) => {
-                            for channel in &requested_channels {
-                                let join = twitch_message::encode::join(channel);
-                                if encoder.encode(join).is_err() {
-                                    break 'inner;
-                                }
+                            failures = 0;
+
+                            let joins = requested_channels
+                                .iter()
+                                .map(|c| twitch_message::encode::join(c));
+                            if let Err(err) = encoder.encode_all(joins) {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
-                            if encoder.flush().await.is_err() {
-                                break 'inner;
+                            if let Err(err) = encoder.flush().await {
+                                break 'inner DisconnectReason::Error(err.to_string());
                             }
 
-                            let user = User {
-                                color: msg.color().unwrap_or_default(),
-                                user_id: msg.user_id().expect("we must have a user-id").to_string(),
-                                name: our_name.clone().expect("we must have a user name"),
+                            let user = match (msg.user_id(), our_name.clone()) {
+                                (Some(user_id), Some(name)) => Some(User {
+                                    color: msg.color().unwrap_or_default(),
+                                    user_id: user_id.to_string(),
+                                    name,
+                                }),
+                                _ => {
+                                    let message = String::from(
+                                        "the server didn't say who we are, sent messages won't be shown",
+                                    );
+                                    let notice = Response::Notice {
+                                        channel: None,
+                                        message,
+                                    };
+                                    if resp.send(notice).await.is_err() {
+                                        break 'outer;
+                                    }
+                                    None
+                                }
                             };
 
-                            our_user.replace(user.clone());
+                            our_user.clone_from(&user);
 
                             if resp.send(Response::Connected { user }).await.is_err() {
                                 break 'outer;
@@ -236,17 +830,20 @@ pub fn connect(
                         }
 
                         M::Privmsg(msg) => {
+                            let (data, action) = sanitize(&msg.data);
                             let message = Message {
                                 sender: User {
                                     color: msg.color().unwrap_or_default(),
-                                    user_id: msg
-                                        .user_id()
-                                        .expect("user must have a user-id")
-                                        .to_string(),
+                                    user_id: msg.user_id().unwrap_or_default().to_string(),
                                     name: msg.sender.to_string(),
                                 },
                                 channel: msg.channel.to_string(),
-                                data: msg.data.to_string(),
+                                data,
+                                timestamp: chrono::Utc::now(),
+                                roles: Roles::from_raw(&msg.raw),
+                                id: tag(&msg.raw, "id").map(ToString::to_string),
+                                tags: tag_map(&msg.raw),
+                                action,
                             };
 
                             if resp.send(Response::Message { message }).await.is_err() {
@@ -257,76 +854,251 @@ pub fn connect(
                         M::Message(msg)
                             if matches!(msg.kind, MessageKind::Unknown(Cow::Borrowed("JOIN"))) =>
                         {
-                            if msg.prefix.as_name_str() == our_name.as_deref() {
-                                if let Some(channel) = msg.args.get(0) {
-                                    if requested_channels.insert(channel.to_string()) {
-                                        if resp
-                                            .send(Response::JoinChannel {
-                                                channel: channel.to_string(),
-                                            })
-                                            .await
-                                            .is_err()
-                                        {
-                                            break 'outer;
-                                        }
-                                    }
+                            let Some(channel) = msg.args.first() else {
+                                continue;
+                            };
+
+                            if msg.prefix.as_name_str() != our_name.as_deref() {
+                                let membership = Response::Membership {
+                                    channel: channel.to_string(),
+                                    joined: true,
+                                };
+                                if resp.send(membership).await.is_err() {
+                                    break 'outer;
                                 }
+                                continue;
+                            }
+
+                            // this is also sent when re-joining after a reconnect
+                            requested_channels.insert(channel.to_string());
+                            if resp
+                                .send(Response::JoinChannel {
+                                    channel: channel.to_string(),
+                                })
+                                .await
+                                .is_err()
+                            {
+                                break 'outer;
                             }
                         }
 
                         M::Message(msg)
                             if matches!(msg.kind, MessageKind::Unknown(Cow::Borrowed("PART"))) =>
                         {
-                            if msg.prefix.as_name_str() == our_name.as_deref() {
-                                if let Some(channel) = msg.args.get(0) {
-                                    if resp
-                                        .send(Response::PartChannel {
-                                            channel: channel.to_string(),
-                                        })
-                                        .await
-                                        .is_err()
-                                    {
-                                        break 'outer;
-                                    }
-                                    requested_channels.remove(&**channel);
+                            let Some(channel) = msg.args.first() else {
+                                continue;
+                            };
+
+                            if msg.prefix.as_name_str() != our_name.as_deref() {
+                                let membership = Response::Membership {
+                                    channel: channel.to_string(),
+                                    joined: false,
+                                };
+                                if resp.send(membership).await.is_err() {
+                                    break 'outer;
                                 }
+                                continue;
+                            }
+
+                            if resp
+                                .send(Response::PartChannel {
+                                    channel: channel.to_string(),
+                                })
+                                .await
+                                .is_err()
+                            {
+                                break 'outer;
                             }
+                            requested_channels.remove(&**channel);
                         }
 
                         _ => {}
                     }
                 }
-            }
+            };
 
-            if resp.send(Response::Disconnected).await.is_err() {
+            // asking to reconnect (or pausing) isn't a failure, so it's retried right away
+            let idle = reason == DisconnectReason::Idle;
+            let replay_ended = reason == DisconnectReason::ReplayEnded;
+            if reason != DisconnectReason::Reconnect && !idle && !replay_ended {
+                failures += 1;
+            }
+            if resp.send(Response::Disconnected { reason }).await.is_err() {
                 break 'outer;
             }
 
-            smol::Timer::after(std::time::Duration::from_secs(3)).await;
+            // there's nothing left to read, so wait to be asked to start over (or quit)
+            while replay_ended {
+                match req.recv().await {
+                    Ok(Request::Disconnect { reconnect: true }) => break,
+                    Ok(Request::Disconnect { reconnect: false }) | Err(..) => break 'outer,
+                    Ok(..) => {}
+                }
+            }
+
+            // nothing is sent while idle, joins and parts are remembered for when we're back
+            while idle {
+                match req.recv().await {
+                    Ok(Request::Resume) => break,
+                    Ok(Request::JoinChannels { channels }) => requested_channels.extend(channels),
+                    Ok(Request::PartChannel { channel }) => {
+                        requested_channels.remove(&channel);
+                    }
+                    Ok(Request::Disconnect { reconnect: false }) | Err(..) => break 'outer,
+                    Ok(..) => {}
+                }
+            }
         }
 
         anyhow::Result::Ok(())
     })
 }
 
+struct PendingMessage {
+    user: User,
+    data: String,
+    sent: Instant,
+}
+
+/// Messages we've sent, waiting for the server to echo them back via USERSTATE
+#[derive(Default)]
+struct PendingMessages {
+    map: HashMap<String, VecDeque<PendingMessage>>,
+}
+
+impl PendingMessages {
+    const MAX_PER_CHANNEL: usize = 16;
+    const MAX_AGE: Duration = Duration::from_secs(30);
+
+    fn push(&mut self, channel: String, user: User, data: String) {
+        self.evict_stale();
+
+        let queue = self.map.entry(channel).or_default();
+        if queue.len() == Self::MAX_PER_CHANNEL {
+            queue.pop_front();
+        }
+        queue.push_back(PendingMessage {
+            user,
+            data,
+            sent: Instant::now(),
+        });
+    }
+
+    fn pop(&mut self, channel: &str) -> Option<PendingMessage> {
+        self.evict_stale();
+
+        let queue = self.map.get_mut(channel)?;
+        let msg = queue.pop_front();
+        if queue.is_empty() {
+            self.map.remove(channel);
+        }
+        msg
+    }
+
+    /// Drops messages whose echo never arrived, so they can't be matched against a much later one
+    fn evict_stale(&mut self) {
+        self.map.retain(|_, queue| {
+            queue.retain(|msg| msg.sent.elapsed() < Self::MAX_AGE);
+            !queue.is_empty()
+        });
+    }
+}
+
 pub struct Config {
     pub name: String,
     pub oauth: String,
+    /// Channels to join on connect
+    pub channels: Vec<String>,
+    /// Capabilities to request, dropping `membership` saves a lot of traffic in big channels
+    pub capabilities: Vec<Capability>,
+    /// How many times to try reconnecting before giving up, `None` to keep trying forever
+    pub max_reconnects: Option<u32>,
+    /// How long to wait before reconnecting, at least [`Config::MIN_RECONNECT_DELAY`]
+    pub reconnect_delay: Duration,
+    /// A captured log of IRC lines to play back instead of connecting, see [`Replay`]
+    pub replay: Option<PathBuf>,
 }
 
 impl Config {
+    /// Reconnecting any faster than this just hammers the server
+    pub const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
     pub fn from_env() -> anyhow::Result<Self> {
         fn get(key: &str) -> anyhow::Result<String> {
             std::env::var(key).map_err(|_| anyhow::anyhow!("`{key}` must exist in the environment"))
         }
 
+        // a replay doesn't log in, so it doesn't need credentials
+        let replay = std::env::var("TWITCH_REPLAY").ok().map(PathBuf::from);
+        let credential = |key| match replay {
+            Some(..) => Ok(std::env::var(key).unwrap_or_default()),
+            None => get(key),
+        };
+
         Ok(Self {
-            name: get("TWITCH_NAME")?,
-            oauth: get("TWITCH_OAUTH")?,
+            name: credential("TWITCH_NAME")?,
+            oauth: credential("TWITCH_OAUTH")?,
+            channels: std::env::var("TWITCH_CHANNELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| format!("#{}", s.trim_start_matches('#').to_lowercase()))
+                .collect(),
+            capabilities: match std::env::var("TWITCH_CAPABILITIES") {
+                Ok(caps) => Self::parse_capabilities(&caps)?,
+                Err(..) => twitch_message::encode::ALL_CAPABILITIES.to_vec(),
+            },
+            max_reconnects: match std::env::var("TWITCH_MAX_RECONNECTS") {
+                Ok(max) => Some(max.trim().parse().map_err(|_| {
+                    anyhow::anyhow!("`TWITCH_MAX_RECONNECTS` must be a positive number")
+                })?),
+                Err(..) => None,
+            },
+            reconnect_delay: match std::env::var("TWITCH_RECONNECT_DELAY") {
+                Ok(secs) => {
+                    let secs = secs.trim().parse().map_err(|_| {
+                        anyhow::anyhow!("`TWITCH_RECONNECT_DELAY` must be a number of seconds")
+                    })?;
+                    let delay = Duration::from_secs(secs);
+                    anyhow::ensure!(
+                        delay >= Self::MIN_RECONNECT_DELAY,
+                        "`TWITCH_RECONNECT_DELAY` must be at least {}s",
+                        Self::MIN_RECONNECT_DELAY.as_secs()
+                    );
+                    delay
+                }
+                Err(..) => Duration::from_secs(3),
+            },
+            replay,
         })
     }
+
+    /// Parses a list like `tags,commands`, `commands` is required as we rely on GLOBALUSERSTATE
+    fn parse_capabilities(caps: &str) -> anyhow::Result<Vec<Capability>> {
+        let mut out = vec![];
+        for cap in caps.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let cap = match &*cap.to_ascii_lowercase() {
+                "tags" => Capability::Tags,
+                "commands" => Capability::Commands,
+                "membership" => Capability::Membership,
+                _ => anyhow::bail!(
+                    "unknown capability `{cap}`, expected `tags`, `commands` or `membership`"
+                ),
+            };
+            if !out.contains(&cap) {
+                out.push(cap);
+            }
+        }
+
+        if !out.contains(&Capability::Commands) {
+            anyhow::bail!("`TWITCH_CAPABILITIES` must include `commands`")
+        }
+        Ok(out)
+    }
 }
 
+/// Encoded messages are buffered until they're written together by [`AsyncEncoder::flush`]
 struct AsyncEncoder<W> {
     buf: Vec<u8>,
     writer: BufWriter<W>,
@@ -344,6 +1116,14 @@ impl<W: AsyncWrite + 'static + Unpin> AsyncEncoder<W> {
         self.buf.encode_msg(msg).map_err(Into::into)
     }
 
+    /// Encodes each of `msgs`, so they're sent in one write on the next flush
+    fn encode_all<M>(&mut self, msgs: impl IntoIterator<Item = M>) -> anyhow::Result<()>
+    where
+        M: twitch_message::encode::Encodable,
+    {
+        msgs.into_iter().try_for_each(|msg| self.encode(msg))
+    }
+
     async fn flush(&mut self) -> anyhow::Result<()> {
         if self.buf.is_empty() {
             return Ok(());
@@ -356,39 +1136,291 @@ impl<W: AsyncWrite + 'static + Unpin> AsyncEncoder<W> {
     }
 }
 
+/// Skips the tags and prefix of a line, leaving the command and its parameters
+fn command_and_params(raw: &str) -> &str {
+    let mut raw = raw.trim_end();
+    for sigil in ['@', ':'] {
+        if raw.starts_with(sigil) {
+            raw = raw.split_once(' ').map_or("", |(_, rest)| rest);
+        }
+    }
+    raw
+}
+
+/// The capabilities acknowledged by a `CAP * ACK :<caps>` line
+fn parse_cap_ack(raw: &str) -> Option<impl Iterator<Item = &str>> {
+    let rest = command_and_params(raw).strip_prefix("CAP ")?;
+    let (_, caps) = rest.split_once(" ACK ")?;
+    Some(caps.trim_start_matches(':').split_whitespace())
+}
+
+/// Parses `HOSTTARGET #channel :<target|-> [viewers]` into its channel, target and viewers
+fn parse_host_target(raw: &str) -> Option<(String, Option<String>, Option<u32>)> {
+    let rest = command_and_params(raw).strip_prefix("HOSTTARGET ")?;
+    let (channel, rest) = rest.split_once(' ')?;
+
+    let mut parts = rest.trim_start_matches(':').split_whitespace();
+    let target = parts.next()?;
+    let target = (target != "-").then(|| target.to_string());
+    let viewers = parts.next().and_then(|viewers| viewers.parse().ok());
+
+    Some((channel.to_string(), target, viewers))
+}
+
+/// Parses `:<sender> WHISPER <us> :<data>` into a message in the `@sender` pseudo-channel
+fn parse_whisper(raw: &str) -> Option<Message> {
+    let rest = raw.trim_end();
+    let rest = match rest.strip_prefix('@') {
+        Some(rest) => rest.split_once(' ')?.1,
+        None => rest,
+    };
+
+    let (prefix, rest) = rest.strip_prefix(':')?.split_once(' ')?;
+    let (_, data) = rest.strip_prefix("WHISPER ")?.split_once(" :")?;
+    let login = prefix.split_once('!').map_or(prefix, |(name, _)| name);
+    let (data, action) = sanitize(data);
+
+    Some(Message {
+        sender: User {
+            color: tag(raw, "color").and_then(parse_color).unwrap_or_default(),
+            user_id: tag(raw, "user-id").unwrap_or_default().to_string(),
+            name: tag(raw, "display-name")
+                .filter(|name| !name.is_empty())
+                .unwrap_or(login)
+                .to_string(),
+        },
+        channel: format!("@{}", login.to_lowercase()),
+        data,
+        timestamp: chrono::Utc::now(),
+        roles: Roles::default(),
+        id: tag(raw, "message-id").map(ToString::to_string),
+        tags: tag_map(raw),
+        action,
+    })
+}
+
+/// Unwraps a CTCP `ACTION` (a `/me`) and drops control characters, which would garble the terminal
+///
+/// mIRC color codes (`\x03` and the colors after it) are dropped too. Returns the text, and whether
+/// it was an action
+fn sanitize(data: &str) -> (String, bool) {
+    let (data, action) = match data.strip_prefix("\x01ACTION ") {
+        Some(data) => (data.strip_suffix('\x01').unwrap_or(data), true),
+        None => (data, false),
+    };
+
+    let mut out = String::with_capacity(data.len());
+    let mut chars = data.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            // `\x03[fg[,bg]]`, where each color is up to two digits
+            '\x03' => {
+                if skip_digits(&mut chars) > 0 {
+                    let mut rest = chars.clone();
+                    if rest.next() == Some(',') && skip_digits(&mut rest) > 0 {
+                        chars = rest;
+                    }
+                }
+            }
+            '\t' => out.push(' '),
+            ch if ch.is_control() => {}
+            ch => out.push(ch),
+        }
+    }
+    (out, action)
+}
+
+/// Splits a `/me` off a message we sent, returning the text and whether it's an action
+fn own_action(data: String) -> (String, bool) {
+    match data.strip_prefix("/me ") {
+        Some(text) => (text.to_string(), true),
+        None => (data, false),
+    }
+}
+
+/// Skips up to two digits, returning how many were skipped
+fn skip_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> usize {
+    let mut skipped = 0;
+    while skipped < 2 && chars.next_if(char::is_ascii_digit).is_some() {
+        skipped += 1;
+    }
+    skipped
+}
+
+/// Parses a `#rrggbb` color tag
+fn parse_color(hex: &str) -> Option<twitch_message::Color> {
+    let hex = hex.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(twitch_message::Color(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+    ))
+}
+
+/// The raw value of the tag `name`, if the line has it
+fn tag<'a>(raw: &'a str, name: &str) -> Option<&'a str> {
+    tags(raw).find_map(|(key, val)| (key == name).then_some(val))
+}
+
+/// Iterates the raw (still escaped) IRCv3 tags of a line
+fn tags(raw: &str) -> impl Iterator<Item = (&str, &str)> {
+    raw.strip_prefix('@')
+        .and_then(|s| s.split_once(' '))
+        .map(|(tags, _)| tags)
+        .unwrap_or_default()
+        .split(';')
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.split_once('=').unwrap_or((tag, "")))
+}
+
+/// The tags of a line, with their values unescaped
+fn tag_map(raw: &str) -> HashMap<String, String> {
+    tags(raw)
+        .map(|(key, val)| (key.to_string(), unescape_tag(val)))
+        .collect()
+}
+
+/// Undoes the IRCv3 tag value escaping, e.g. `\s` for a space
+fn unescape_tag(val: &str) -> String {
+    let mut out = String::with_capacity(val.len());
+    let mut chars = val.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        // an unknown escape is the character itself, and a trailing `\` is dropped
+        match chars.next() {
+            Some(':') => out.push(';'),
+            Some('s') => out.push(' '),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(ch) => out.push(ch),
+            None => {}
+        }
+    }
+    out
+}
+
 async fn register(
     config: &Config,
     encoder: &mut AsyncEncoder<impl AsyncWrite + 'static + Unpin>,
 ) -> anyhow::Result<()> {
-    let msg = twitch_message::encode::register(
-        &config.name,
-        &config.oauth,
-        twitch_message::encode::ALL_CAPABILITIES,
-    );
+    let msg = twitch_message::encode::register(&config.name, &config.oauth, &config.capabilities);
     encoder.encode(msg)?;
     encoder.flush().await
 }
 
+/// Plays back a captured log of IRC lines as if they came from the server
+///
+/// Lines are spaced out by their `tmi-sent-ts` tags, so a replay runs at about the speed it was captured
+struct Replay {
+    lines: std::vec::IntoIter<String>,
+    /// What's left of the current line, for reads smaller than it
+    pending: VecDeque<u8>,
+    /// When the previous line was sent, in unix milliseconds
+    last_sent: Option<i64>,
+}
+
+impl Replay {
+    /// The delay between lines without a timestamp
+    const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+    /// Longer gaps in the log are cut short
+    const MAX_DELAY: Duration = Duration::from_secs(2);
+
+    /// Reads the log at `path`, along with the `<path>.sent` file that what we send is written to instead
+    fn open(path: &Path) -> std::io::Result<(Self, std::fs::File)> {
+        let lines = std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        let mut sent = path.as_os_str().to_owned();
+        sent.push(".sent");
+        let sent = std::fs::File::create(sent)?;
+
+        let replay = Self {
+            lines: lines.into_iter(),
+            pending: VecDeque::new(),
+            last_sent: None,
+        };
+        Ok((replay, sent))
+    }
+
+    fn delay(&mut self, line: &str) -> Duration {
+        let Some(sent) = tag(line, "tmi-sent-ts").and_then(|ts| ts.parse::<i64>().ok()) else {
+            return Self::DEFAULT_DELAY;
+        };
+
+        let delay = match self.last_sent.replace(sent) {
+            Some(last) => Duration::from_millis(sent.saturating_sub(last).max(0) as u64),
+            None => Duration::ZERO,
+        };
+        delay.min(Self::MAX_DELAY)
+    }
+}
+
+// this blocks while waiting for the next line, so it's read through `smol::Unblock`
+impl std::io::Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending.is_empty() {
+            let Some(line) = self.lines.next() else {
+                return Ok(0);
+            };
+            std::thread::sleep(self.delay(&line));
+            self.pending.extend(line.bytes().chain(*b"\r\n"));
+        }
+
+        let len = buf.len().min(self.pending.len());
+        for (dst, src) in buf.iter_mut().zip(self.pending.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+/// Why [`Reader::read_line`] couldn't read a line
+#[derive(Debug)]
+enum ReadError {
+    /// The server closed the connection
+    Eof,
+    Io(std::io::Error),
+}
+
 struct Reader<R> {
-    buf: String,
-    reader: smol::io::BufReader<R>,
+    buf: Vec<u8>,
+    reader: R,
 }
 
 impl<R: AsyncRead + 'static + Unpin> Reader<R> {
     fn new(read: R) -> Self {
         Self {
-            buf: String::with_capacity(1024),
-            reader: BufReader::new(read),
+            buf: Vec::with_capacity(1024),
+            reader: read,
         }
     }
 
-    async fn read_line(&mut self) -> anyhow::Result<String> {
-        let pos = self.reader.read_line(&mut self.buf).await?;
-        anyhow::ensure!(pos != 0, "unexpected EOF");
+    /// Reads a single line, without its trailing `\r\n`
+    ///
+    /// Bytes after the line, or read before this future was dropped, are kept for the next call
+    async fn read_line(&mut self) -> Result<String, ReadError> {
+        loop {
+            if let Some(pos) = self.buf.iter().position(|&c| c == b'\n') {
+                let line = self.buf.drain(..=pos).collect::<Vec<_>>();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+            }
 
-        let mut buf = std::mem::take(&mut self.buf);
-        buf.truncate(pos);
-        Ok(buf)
+            let mut chunk = [0; 1024];
+            let read = self.reader.read(&mut chunk).await.map_err(ReadError::Io)?;
+            if read == 0 {
+                return Err(ReadError::Eof);
+            }
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
     }
 }
 
@@ -441,3 +1473,260 @@ where
 {
     Select2 { left, right }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one chunk per read, like a socket would
+    struct Chunks(VecDeque<&'static [u8]>);
+
+    impl AsyncRead for Chunks {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let Some(chunk) = self.0.pop_front() else {
+                return Poll::Ready(Ok(0));
+            };
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Poll::Ready(Ok(chunk.len()))
+        }
+    }
+
+    /// Records what's written to it, shared between clones
+    #[derive(Clone, Default)]
+    struct Counting {
+        writes: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+        flushes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl AsyncWrite for Counting {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.writes.lock().unwrap().push(buf.to_vec());
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.flushes
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn reader(chunks: &[&'static [u8]]) -> Reader<Chunks> {
+        Reader::new(Chunks(chunks.iter().copied().collect()))
+    }
+
+    fn read_line(reader: &mut Reader<Chunks>) -> Option<String> {
+        smol::block_on(reader.read_line()).ok()
+    }
+
+    #[test]
+    fn line_split_across_reads() {
+        let mut reader = reader(&[b"PING :tm", b"i.twitch.tv\r", b"\n"]);
+        assert_eq!(
+            read_line(&mut reader).as_deref(),
+            Some("PING :tmi.twitch.tv")
+        );
+        assert!(matches!(
+            smol::block_on(reader.read_line()),
+            Err(ReadError::Eof)
+        ));
+    }
+
+    #[test]
+    fn several_lines_in_one_read() {
+        let mut reader = reader(&[b"PING :a\r\nPING :b\r\nPI", b"NG :c\r\n"]);
+        assert_eq!(read_line(&mut reader).as_deref(), Some("PING :a"));
+        assert_eq!(read_line(&mut reader).as_deref(), Some("PING :b"));
+        assert_eq!(read_line(&mut reader).as_deref(), Some("PING :c"));
+        assert!(matches!(
+            smol::block_on(reader.read_line()),
+            Err(ReadError::Eof)
+        ));
+    }
+
+    #[test]
+    fn encode_all_is_one_write_and_flush() {
+        let writer = Counting::default();
+        let mut encoder = AsyncEncoder::new(writer.clone());
+
+        let channels = ["#foo", "#bar", "#baz"];
+        encoder
+            .encode_all(channels.iter().map(|c| twitch_message::encode::join(c)))
+            .unwrap();
+        assert!(writer.writes.lock().unwrap().is_empty());
+
+        smol::block_on(encoder.flush()).unwrap();
+
+        let writes = writer.writes.lock().unwrap();
+        assert_eq!(writes.len(), 1);
+        let written = String::from_utf8_lossy(&writes[0]);
+        let lines = written.lines().map(str::trim_end).collect::<Vec<_>>();
+        assert_eq!(lines, ["JOIN #foo", "JOIN #bar", "JOIN #baz"]);
+        assert_eq!(writer.flushes.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn sanitize_drops_control_characters() {
+        assert_eq!(
+            sanitize("hi\x07 there\x1b[1m!"),
+            (String::from("hi there[1m!"), false)
+        );
+        assert_eq!(
+            sanitize("\x02bold\x02 \x1funderline\x0f"),
+            (String::from("bold underline"), false)
+        );
+        assert_eq!(sanitize("a\tb\r\n"), (String::from("a b"), false));
+        assert_eq!(
+            sanitize("héllo 日本 👋"),
+            (String::from("héllo 日本 👋"), false)
+        );
+    }
+
+    #[test]
+    fn sanitize_drops_color_codes() {
+        assert_eq!(
+            sanitize("\x0304red\x03 plain"),
+            (String::from("red plain"), false)
+        );
+        assert_eq!(sanitize("\x034,12both"), (String::from("both"), false));
+        // a comma without a background color is text
+        assert_eq!(sanitize("\x034,hi"), (String::from(",hi"), false));
+        // only two digits are part of the color
+        assert_eq!(sanitize("\x03123"), (String::from("3"), false));
+    }
+
+    #[test]
+    fn sanitize_keeps_actions() {
+        assert_eq!(
+            sanitize("\x01ACTION waves\x01"),
+            (String::from("waves"), true)
+        );
+        assert_eq!(
+            sanitize("\x01ACTION waves \x07loudly\x01"),
+            (String::from("waves loudly"), true)
+        );
+        // some clients leave off the closing \x01
+        assert_eq!(sanitize("\x01ACTION waves"), (String::from("waves"), true));
+        assert_eq!(
+            sanitize("\x01VERSION\x01"),
+            (String::from("VERSION"), false)
+        );
+    }
+
+    /// A limit with `count` messages sent `ago`
+    fn sent(count: usize, ago: Duration) -> RateLimit {
+        let at = Instant::now() - ago;
+        RateLimit {
+            sent: std::iter::repeat(at).take(count).collect(),
+        }
+    }
+
+    #[test]
+    fn rate_limit_capacity_depends_on_roles() {
+        assert_eq!(RateLimit::capacity(None), RateLimit::CAPACITY);
+        let roles = Roles {
+            subscriber: true,
+            ..Roles::default()
+        };
+        assert_eq!(RateLimit::capacity(Some(&roles)), RateLimit::CAPACITY);
+        for roles in [
+            Roles {
+                broadcaster: true,
+                ..Roles::default()
+            },
+            Roles {
+                moderator: true,
+                ..Roles::default()
+            },
+            Roles {
+                vip: true,
+                ..Roles::default()
+            },
+        ] {
+            assert_eq!(
+                RateLimit::capacity(Some(&roles)),
+                RateLimit::ELEVATED_CAPACITY
+            );
+        }
+    }
+
+    #[test]
+    fn rate_limit_is_used_up() {
+        let limit = RateLimit::default();
+        assert_eq!(limit.remaining(RateLimit::CAPACITY), RateLimit::CAPACITY);
+        assert_eq!(limit.next_available(RateLimit::CAPACITY), None);
+
+        let limit = sent(RateLimit::CAPACITY, Duration::from_secs(10));
+        assert_eq!(limit.remaining(RateLimit::CAPACITY), 0);
+        let wait = limit.next_available(RateLimit::CAPACITY).unwrap();
+        assert!(wait <= Duration::from_secs(20) && wait > Duration::from_secs(19));
+        // the same messages leave room in channels with the higher limit
+        assert_eq!(limit.remaining(RateLimit::ELEVATED_CAPACITY), 80);
+        assert_eq!(limit.next_available(RateLimit::ELEVATED_CAPACITY), None);
+    }
+
+    #[test]
+    fn rate_limit_frees_up_as_messages_age() {
+        let mut limit = sent(RateLimit::CAPACITY, RateLimit::WINDOW);
+        assert_eq!(limit.remaining(RateLimit::CAPACITY), RateLimit::CAPACITY);
+
+        limit.record();
+        assert_eq!(
+            limit.remaining(RateLimit::CAPACITY),
+            RateLimit::CAPACITY - 1
+        );
+        // only what's in the window is kept
+        assert_eq!(limit.sent.len(), 1);
+    }
+
+    #[test]
+    fn rate_limit_is_exhausted_for_every_capacity() {
+        let mut limit = RateLimit::default();
+        limit.exhaust();
+        for capacity in [RateLimit::CAPACITY, RateLimit::ELEVATED_CAPACITY] {
+            assert_eq!(limit.remaining(capacity), 0);
+            assert!(limit.next_available(capacity).is_some());
+        }
+    }
+
+    #[test]
+    fn host_target() {
+        assert_eq!(
+            parse_host_target(":tmi.twitch.tv HOSTTARGET #foo :bar 42"),
+            Some((String::from("#foo"), Some(String::from("bar")), Some(42)))
+        );
+        // twitch leaves the viewers off sometimes
+        assert_eq!(
+            parse_host_target(":tmi.twitch.tv HOSTTARGET #foo :bar"),
+            Some((String::from("#foo"), Some(String::from("bar")), None))
+        );
+        // `-` ends hosting
+        assert_eq!(
+            parse_host_target(":tmi.twitch.tv HOSTTARGET #foo :- 0"),
+            Some((String::from("#foo"), None, Some(0)))
+        );
+        assert_eq!(
+            parse_host_target(":tmi.twitch.tv HOSTTARGET #foo :-"),
+            Some((String::from("#foo"), None, None))
+        );
+        assert_eq!(parse_host_target(":tmi.twitch.tv NOTICE #foo :hi"), None);
+    }
+}